@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS saved_searches_table (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users_table(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    query TEXT NOT NULL,
+    time_creation TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS saved_searches_user_idx ON saved_searches_table (user_id);
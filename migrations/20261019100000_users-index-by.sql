@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE users_table ADD COLUMN index_by TEXT NOT NULL DEFAULT 'last_name';
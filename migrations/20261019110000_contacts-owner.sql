@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE contacts_table ADD COLUMN user_id INTEGER REFERENCES users_table(id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS contacts_user_idx ON contacts_table (user_id);
+CREATE TABLE IF NOT EXISTS archive_downloads_table (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    user_agent TEXT NOT NULL,
+    time_download TEXT NOT NULL
+);
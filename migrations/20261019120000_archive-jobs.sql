@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS archive_jobs_table (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users_table(id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    progress REAL NOT NULL DEFAULT 0,
+    file_name TEXT NOT NULL,
+    size INTEGER NOT NULL DEFAULT 0,
+    dismissed BOOLEAN NOT NULL DEFAULT FALSE,
+    time_creation TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS archive_jobs_user_idx ON archive_jobs_table (user_id);
@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS calendar_tokens_table (
+    user_id INTEGER PRIMARY KEY NOT NULL REFERENCES users_table(id) ON DELETE CASCADE,
+    token TEXT NOT NULL UNIQUE,
+    time_creation TEXT NOT NULL
+);
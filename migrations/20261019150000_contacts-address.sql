@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE contacts_table ADD COLUMN address TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS export_templates_table (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users_table(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    extension TEXT NOT NULL,
+    body TEXT NOT NULL,
+    time_creation TEXT NOT NULL,
+    UNIQUE (user_id, name)
+);
@@ -0,0 +1,22 @@
+-- Usernames are unique regardless of case. Accounts differing only in case
+-- must be renamed by hand before this runs; the app lists them on startup,
+-- and this query finds them too:
+--   SELECT id, username FROM users_table
+--   WHERE username COLLATE NOCASE IN (
+--       SELECT username FROM users_table
+--       GROUP BY username COLLATE NOCASE
+--       HAVING COUNT(*) > 1
+--   );
+CREATE TEMP TABLE username_clashes (id INTEGER);
+CREATE TEMP TRIGGER username_clashes_abort BEFORE INSERT ON username_clashes
+BEGIN
+    SELECT RAISE(ABORT, 'Some usernames differ only in case; rename all but one of each before upgrading, see migrations/20261019180000_users-username-nocase.sql');
+END;
+INSERT INTO username_clashes
+SELECT id FROM users_table
+WHERE id NOT IN (
+    SELECT MIN(id) FROM users_table GROUP BY username COLLATE NOCASE
+);
+DROP TRIGGER username_clashes_abort;
+DROP TABLE username_clashes;
+CREATE UNIQUE INDEX IF NOT EXISTS users_username_nocase ON users_table (username COLLATE NOCASE);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE users_table ADD COLUMN display_name TEXT NOT NULL DEFAULT '';
+ALTER TABLE users_table ADD COLUMN email TEXT NOT NULL DEFAULT '';
+ALTER TABLE users_table ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
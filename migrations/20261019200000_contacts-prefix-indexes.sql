@@ -0,0 +1,4 @@
+-- Add migration script here
+CREATE INDEX IF NOT EXISTS contacts_first_name_idx ON contacts_table (first_name COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS contacts_last_name_idx ON contacts_table (last_name COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS contacts_email_idx ON contacts_table (email COLLATE NOCASE);
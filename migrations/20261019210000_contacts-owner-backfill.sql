@@ -0,0 +1,5 @@
+-- Contacts from before accounts owned them belong to the first account.
+-- Without any account yet, the first one to sign up adopts them.
+UPDATE contacts_table
+SET user_id = (SELECT MIN(id) FROM users_table)
+WHERE user_id IS NULL;
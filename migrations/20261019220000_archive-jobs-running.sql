@@ -0,0 +1,9 @@
+-- At most one running archive job per user.
+UPDATE archive_jobs_table
+SET status = 'Failed'
+WHERE status = 'Running'
+AND id NOT IN (
+    SELECT MAX(id) FROM archive_jobs_table WHERE status = 'Running' GROUP BY user_id
+);
+CREATE UNIQUE INDEX IF NOT EXISTS archive_jobs_running_idx ON archive_jobs_table (user_id)
+WHERE status = 'Running';
@@ -3,12 +3,13 @@ pub mod models;
 pub mod routers;
 
 use crate::models::*;
-use axum::Router;
+use axum::{middleware, Router};
 use axum_login::AuthManagerLayerBuilder;
 use axum_messages::MessagesManagerLayer;
 use dotenv::dotenv;
 use routers::*;
 use sqlx::{sqlite::SqlitePool, Pool, Sqlite};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tokio::sync::RwLock;
@@ -25,7 +26,21 @@ async fn main() -> anyhow::Result<()> {
     let db_url: String = std::env::var("DATABASE_URL")?;
     //let db_url = "sqlite:db/contacts.db";
     let pool: Pool<Sqlite> = SqlitePool::connect(&db_url).await?;
+    let clashes = username_case_clashes(&pool).await?;
+    if !clashes.is_empty() {
+        anyhow::bail!(
+            "Usernames must be unique regardless of case; rename all but one of these accounts before upgrading: {}",
+            clashes.join(", ")
+        );
+    }
     sqlx::migrate!("./migrations").run(&pool).await?;
+    ArchiveJob::fail_interrupted(&pool).await?;
+    let backup_policy = BackupPolicy::from_env()?;
+    let _backup_task = tokio::task::spawn(run_backup_schedule(pool.clone(), backup_policy));
+    let _expiry_task = tokio::task::spawn(run_expiry_sweeper());
+    // Refuse to start on a misconfigured password policy rather than at signup.
+    PasswordPolicy::from_env()?;
+    base_url()?;
 
     //let session_store = MemoryStore::default();
     let session_store = SqliteStore::new(pool.clone());
@@ -54,19 +69,35 @@ async fn main() -> anyhow::Result<()> {
     let app_state = AppState {
         pool_state: Arc::new(RwLock::new(pool)),
         contact_error_state: Arc::new(RwLock::new(CreationErrorState::default())),
-        archiver_state: Arc::new(RwLock::new(ArchiverState::default())),
+        csv_import_state: Arc::new(RwLock::new(HashMap::new())),
+        maintenance_state: Arc::new(RwLock::new(false)),
     };
     //let app_state = Arc::new(RwLock::new(app_state));
 
     let app = Router::new()
         .merge(index_router())
         .merge(show_router())
+        .merge(suggest_router())
         .merge(contactform_new_router())
         .merge(view_router())
+        .merge(export_router())
         .merge(contactform_edit_router())
+        .merge(import_router())
+        .merge(csv_import_router())
+        .merge(mail_import_router())
+        .merge(saved_searches_router())
         .merge(archive_router())
+        .merge(admin_router())
+        .merge(restore_router())
+        .merge(calendar_router())
+        .merge(account_router())
+        .merge(export_templates_router())
         .merge(utils_router())
         .merge(userform_login_router())
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            maintenance_guard,
+        ))
         .with_state(app_state)
         .layer(MessagesManagerLayer)
         .layer(auth_layer)
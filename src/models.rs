@@ -2,15 +2,15 @@ use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
 use argon2::Argon2;
 use axum::extract::FromRef;
 use axum::{http::StatusCode, response::IntoResponse};
-use axum_macros::FromRef;
-use chrono::{DateTime, Local};
-use rand::Rng;
-use serde::Deserialize;
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use deunicode::{deunicode, deunicode_char};
+use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use sqlx::{Pool, Sqlite};
+use sqlx::{Executor, Pool, Sqlite, SqliteConnection};
+use std::collections::HashSet;
 use std::sync::Arc;
+use strsim::damerau_levenshtein;
 use tokio::sync::RwLock;
-use tokio::time::{sleep, Duration};
 
 // region: APP
 
@@ -18,13 +18,15 @@ use tokio::time::{sleep, Duration};
 pub struct AppState {
     pub pool_state: PoolStateType,
     pub contact_error_state: CreationErrorStateType,
-    pub archiver_state: ArchiverStateType,
+    pub csv_import_state: CsvImportStateType,
+    pub maintenance_state: MaintenanceStateType,
 }
 
 //pub type AppStateType = Arc<RwLock<AppState>>;
 pub type PoolStateType = Arc<RwLock<Pool<Sqlite>>>;
 pub type CreationErrorStateType = Arc<RwLock<CreationErrorState>>;
-pub type ArchiverStateType = Arc<RwLock<ArchiverState>>;
+/// Set while a restore runs: the app then only serves reads.
+pub type MaintenanceStateType = Arc<RwLock<bool>>;
 
 pub fn get_time() -> String {
     let time_stamp_now = std::time::SystemTime::now();
@@ -43,9 +45,16 @@ impl FromRef<AppState> for Arc<RwLock<CreationErrorState>> {
         app_state.contact_error_state.clone()
     }
 }
-impl FromRef<AppState> for Arc<RwLock<ArchiverState>> {
-    fn from_ref(app_state: &AppState) -> Arc<RwLock<ArchiverState>> {
-        app_state.archiver_state.clone()
+
+impl FromRef<AppState> for MaintenanceStateType {
+    fn from_ref(app_state: &AppState) -> MaintenanceStateType {
+        app_state.maintenance_state.clone()
+    }
+}
+
+impl FromRef<AppState> for CsvImportStateType {
+    fn from_ref(app_state: &AppState) -> CsvImportStateType {
+        app_state.csv_import_state.clone()
     }
 }
 
@@ -92,7 +101,7 @@ where
 
 // region: CONTACTS
 
-#[derive(Debug, Default, Clone, Deserialize, FromRow)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, FromRow)]
 pub struct Contact {
     pub id: i64,
     pub first_name: String,
@@ -100,13 +109,149 @@ pub struct Contact {
     pub phone: String,
     pub email: String,
     pub birth_date: String,
+    /// Postal address, one line per row as it goes on an envelope.
+    #[serde(default)]
+    pub address: String,
+    /// Company or organisation the contact works for.
+    #[serde(default)]
+    pub organization: String,
     pub time_creation: String,
+    /// The user who created or imported the contact; never read from forms.
+    #[serde(skip)]
+    pub user_id: Option<i64>,
 }
 
 pub struct Contacts {
     pub contacts: Vec<Contact>,
 }
 
+/// Splits text into words, pairing each original word with its lowercase,
+/// accent-free form ("Müller" -> "muller").
+pub fn fold_words(text: &str) -> Vec<(String, String)> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| (word.to_owned(), deunicode(word).to_lowercase()))
+        .collect()
+}
+
+/// Typo distance between a folded query word and a folded word, also trying the
+/// word's prefix so partially typed words match. Longer query words tolerate
+/// more typos.
+fn word_distance(query: &str, word: &str) -> Option<usize> {
+    let query_len = query.chars().count();
+    let max_distance = match query_len {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    };
+    let prefix = word.chars().take(query_len).collect::<String>();
+    let distance = damerau_levenshtein(query, word).min(damerau_levenshtein(query, &prefix));
+    (distance <= max_distance).then_some(distance)
+}
+
+/// A LIKE pattern matching text that starts with `text`, with its wildcards
+/// escaped by `\`.
+pub fn like_prefix(text: &str) -> String {
+    let mut pattern = String::new();
+    for c in text.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// Letter of the A-Z index a name files under: its first letter without
+/// accents, or '#' for digits, symbols and non-Latin scripts.
+pub fn index_letter(name: &str) -> char {
+    let Some(first) = name.trim().chars().next() else {
+        return '#';
+    };
+    let latin =
+        matches!(first, 'A'..='Z' | 'a'..='z' | '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}');
+    if !latin || !first.is_alphabetic() {
+        return '#';
+    }
+    match deunicode_char(first).and_then(|ascii| ascii.chars().next()) {
+        Some(letter) if letter.is_ascii_alphabetic() => letter.to_ascii_uppercase(),
+        _ => '#',
+    }
+}
+
+/// Which name the A-Z index and name ordering go by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexBy {
+    FirstName,
+    LastName,
+}
+
+impl IndexBy {
+    pub fn from_sort(sort_set: &str) -> Option<IndexBy> {
+        match sort_set {
+            "first_name" => Some(IndexBy::FirstName),
+            "last_name" => Some(IndexBy::LastName),
+            _ => None,
+        }
+    }
+    pub fn sort(&self) -> &'static str {
+        match self {
+            IndexBy::FirstName => "first_name",
+            IndexBy::LastName => "last_name",
+        }
+    }
+    pub fn name<'a>(&self, contact: &'a Contact) -> &'a str {
+        match self {
+            IndexBy::FirstName => &contact.first_name,
+            IndexBy::LastName => &contact.last_name,
+        }
+    }
+}
+
+/// Contacts listed per page in `/contacts/show`.
+pub const PAGE_SIZE: u32 = 8;
+
+/// Days ahead covered by the upcoming birthdays view when none is requested.
+pub const DEFAULT_BIRTHDAY_WINDOW: u32 = 30;
+
+/// Days ahead covered by the upcoming birthdays view: the requested window,
+/// kept within a year.
+pub fn birthday_window(window_p: Option<u32>) -> u32 {
+    window_p.unwrap_or(DEFAULT_BIRTHDAY_WINDOW).clamp(1, 366)
+}
+
+/// Query parameters of `/contacts/show`, also stored as saved searches.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ShowParams {
+    pub search_p: Option<String>,
+    pub page_p: u32,
+    pub birthday_p: u32,
+    pub sort_p: Option<String>,
+    pub window_p: Option<u32>,
+    pub index_p: Option<String>,
+}
+
+/// What autocomplete and contact pickers need to display a contact.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContactSuggestion {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+    pub avatar: String,
+}
+
+impl From<&Contact> for ContactSuggestion {
+    fn from(contact: &Contact) -> Self {
+        ContactSuggestion {
+            id: contact.id,
+            name: format!("{} {}", contact.first_name.trim(), contact.last_name.trim()),
+            email: contact.email.clone(),
+            avatar: contact.initials(),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct CreationErrorState {
     pub first_error: String,
@@ -117,157 +262,593 @@ pub struct CreationErrorState {
     pub birth_error: String,
 }
 
+impl CreationErrorState {
+    /// The non-empty messages, for lists such as import previews.
+    pub fn messages(&self) -> Vec<String> {
+        [
+            &self.first_error,
+            &self.last_error,
+            &self.phone_error,
+            &self.email_error,
+            &self.email_unique_error,
+            &self.birth_error,
+        ]
+        .into_iter()
+        .filter(|error| !error.is_empty())
+        .cloned()
+        .collect()
+    }
+}
+
+/// One page of a `/contacts/show` view with its A-Z jump bar.
+pub struct ContactsPage {
+    pub contacts: Vec<Contact>,
+    pub page: u32,
+    pub max_page: u32,
+    pub letters: Vec<(char, Option<u32>)>,
+    pub suggestion: Option<String>,
+}
+
+/// One `/contacts/show` view worked out in memory from contacts read once:
+/// exact matches, then typo-tolerant ones, the birthday window and the order.
+pub struct ContactsView {
+    /// Every contact of the view in display order, not paginated.
+    pub contacts: Vec<Contact>,
+    /// Closest spelling in names and emails when nothing matched the search
+    /// exactly, e.g. "John" for "Jonh".
+    pub suggestion: Option<String>,
+}
+
+impl ContactsView {
+    pub fn new(
+        contacts_set: Vec<Contact>,
+        search_bar: &str,
+        birthday_set: u32,
+        sort_set: &str,
+        window_set: u32,
+    ) -> ContactsView {
+        let query = fold_words(search_bar)
+            .into_iter()
+            .map(|(_, folded)| folded)
+            .collect::<Vec<String>>();
+        let (mut exact_set, others): (Vec<Contact>, Vec<Contact>) = contacts_set
+            .into_iter()
+            .partition(|contact| contact.matches(search_bar));
+        sort_contacts(&mut exact_set, sort_set, search_bar.is_empty());
+        let mut suggestion = None;
+        let mut fuzzy_count = 0;
+        if !query.is_empty() {
+            let mut fuzzy_set = others
+                .into_iter()
+                .filter_map(|contact| Some((contact.fuzzy_match(&query)?, contact)))
+                .collect::<Vec<((usize, String), Contact)>>();
+            fuzzy_set.sort_by_key(|((distance, _), contact)| (*distance, contact.id));
+            if exact_set.is_empty() {
+                suggestion = fuzzy_set.first().map(|((_, spelling), _)| spelling.clone());
+            }
+            fuzzy_count = fuzzy_set.len();
+            exact_set.extend(fuzzy_set.into_iter().map(|(_, contact)| contact));
+        }
+        // Typo-tolerant matches are marked, so name sorts keep them after exact ones.
+        let exact_count = exact_set.len() - fuzzy_count;
+        let mut contacts_set = exact_set
+            .into_iter()
+            .enumerate()
+            .map(|(position, contact)| (position >= exact_count, contact))
+            .collect::<Vec<(bool, Contact)>>();
+        if birthday_set != 0 {
+            let today = Local::now().date_naive();
+            let mut upcoming_set = contacts_set
+                .into_iter()
+                .filter_map(|(fuzzy, contact)| {
+                    let days = (contact.next_birthday(today)? - today).num_days();
+                    // Today is day 0, so a 7 day window ends the day before a week from now.
+                    (days < window_set as i64).then_some((days, (fuzzy, contact)))
+                })
+                .collect::<Vec<(i64, (bool, Contact))>>();
+            if sort_set.is_empty() {
+                // Stable sort, so ties keep the order above.
+                upcoming_set.sort_by_key(|(days, _)| *days);
+            }
+            contacts_set = upcoming_set
+                .into_iter()
+                .map(|(_, contact)| contact)
+                .collect();
+        }
+        // Names sort with the same folding as the A-Z index, within exact and
+        // typo-tolerant matches each.
+        if let Some(index_by) = IndexBy::from_sort(sort_set) {
+            contacts_set
+                .sort_by_cached_key(|(fuzzy, contact)| (*fuzzy, contact.index_key(index_by)));
+        }
+        ContactsView {
+            contacts: contacts_set
+                .into_iter()
+                .map(|(_, contact)| contact)
+                .collect(),
+            suggestion,
+        }
+    }
+    /// Contacts of one page, with the page clamped to the existing ones, and
+    /// the number of pages.
+    pub fn page(&self, mut page_set: u32) -> (Vec<Contact>, u32, u32) {
+        let max_page = (self.contacts.len() as u32).div_ceil(PAGE_SIZE).max(1);
+        page_set = page_set.clamp(1, max_page);
+        let contacts_set = self
+            .contacts
+            .iter()
+            .skip(((page_set - 1) * PAGE_SIZE) as usize)
+            .take(PAGE_SIZE as usize)
+            .cloned()
+            .collect();
+        (contacts_set, page_set, max_page)
+    }
+    /// The A-Z (and "#") jump bar of the view by `index_by` names: each letter
+    /// with the page its first contact is on in the current order, or `None`
+    /// when no contact files under it.
+    pub fn letter_index(&self, index_by: IndexBy) -> Vec<(char, Option<u32>)> {
+        let mut letters = ('A'..='Z')
+            .chain(['#'])
+            .map(|letter| (letter, None))
+            .collect::<Vec<(char, Option<u32>)>>();
+        for (position, contact) in self.contacts.iter().enumerate() {
+            let letter = index_letter(index_by.name(contact));
+            if let Some((_, page)) = letters.iter_mut().find(|(l, _)| *l == letter) {
+                page.get_or_insert(position as u32 / PAGE_SIZE + 1);
+            }
+        }
+        letters
+    }
+}
+
+/// The order of `search_contacts` in Rust: the requested column without case,
+/// then birth date for the full list, then id.
+fn sort_contacts(contacts_set: &mut [Contact], sort_set: &str, full_list: bool) {
+    contacts_set.sort_by_cached_key(|contact| {
+        let column = match sort_set {
+            "first_name" => contact.first_name.to_lowercase(),
+            "last_name" => contact.last_name.to_lowercase(),
+            "birth_date" => contact.birth_date.to_lowercase(),
+            "created" => contact.time_creation.to_lowercase(),
+            _ => String::new(),
+        };
+        let birth_date = match full_list {
+            true => contact.birth_date.clone(),
+            false => String::new(),
+        };
+        (column, birth_date, contact.id)
+    });
+}
+
 impl Contacts {
-    pub async fn match_contacts(
-        pool: Pool<Sqlite>,
+    /// Every contact of a `/contacts/show` view, in order and without paging.
+    pub async fn all_contacts(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        search_bar: &str,
+        birthday_set: u32,
+        sort_set: &str,
+        window_set: u32,
+    ) -> anyhow::Result<Vec<Contact>> {
+        match Self::filtered_contacts(
+            pool,
+            user_id,
+            search_bar,
+            birthday_set,
+            sort_set,
+            window_set,
+        )
+        .await?
+        {
+            Some(contacts_set) => Ok(contacts_set),
+            None => Self::search_contacts(pool, user_id, search_bar, sort_set, -1, 0).await,
+        }
+    }
+    /// Every contact of views filtered in Rust (fuzzy search, birthday window),
+    /// or `None` for the plain list, which SQL paginates by itself.
+    pub async fn filtered_contacts(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
         search_bar: &str,
-        mut page_set: u32,
         birthday_set: u32,
-    ) -> anyhow::Result<(Contacts, u32, u32, u32)> {
-        let num_of_rows = sqlx::query!(
+        sort_set: &str,
+        window_set: u32,
+    ) -> anyhow::Result<Option<Vec<Contact>>> {
+        if search_bar.is_empty() && birthday_set == 0 {
+            return Ok(None);
+        }
+        let contacts_set = Self::search_contacts(pool, user_id, "", "", -1, 0).await?;
+        let view = ContactsView::new(contacts_set, search_bar, birthday_set, sort_set, window_set);
+        Ok(Some(view.contacts))
+    }
+    /// One page of a `/contacts/show` view. The plain list is paged and
+    /// indexed by SQL; searches and birthday windows are worked out in memory.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn show_contacts(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        search_bar: &str,
+        birthday_set: u32,
+        sort_set: &str,
+        window_set: u32,
+        index_by: IndexBy,
+        page_set: u32,
+    ) -> anyhow::Result<ContactsPage> {
+        if search_bar.is_empty() && birthday_set == 0 {
+            let mut conn = pool.acquire().await?;
+            let count = Self::count_owned(&mut conn, user_id).await?;
+            let max_page = count.div_ceil(PAGE_SIZE).max(1);
+            let page_set = page_set.clamp(1, max_page);
+            let offset = ((page_set - 1) * PAGE_SIZE) as i64;
+            let contacts_set =
+                Self::search_contacts(pool, user_id, "", sort_set, PAGE_SIZE as i64, offset)
+                    .await?;
+            let letters = Self::letter_pages(pool, user_id, sort_set, index_by).await?;
+            return Ok(ContactsPage {
+                contacts: contacts_set,
+                page: page_set,
+                max_page,
+                letters,
+                suggestion: None,
+            });
+        }
+        let contacts_set = Self::search_contacts(pool, user_id, "", "", -1, 0).await?;
+        let view = ContactsView::new(contacts_set, search_bar, birthday_set, sort_set, window_set);
+        let letters = view.letter_index(index_by);
+        let (contacts_set, page_set, max_page) = view.page(page_set);
+        Ok(ContactsPage {
+            contacts: contacts_set,
+            page: page_set,
+            max_page,
+            letters,
+            suggestion: view.suggestion,
+        })
+    }
+    /// Index letter of each initial `index_by` names of `user_id` start with,
+    /// as a JSON object SQL joins on to order and group names by letter.
+    async fn initial_letters(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        index_by: IndexBy,
+    ) -> anyhow::Result<String> {
+        let index_set = index_by.sort();
+        let initials = sqlx::query!(
             r#"
-            SELECT COUNT(*) as count
+            SELECT DISTINCT
+                substr(trim(CASE ?1 WHEN 'last_name' THEN last_name ELSE first_name END), 1, 1)
+                AS "initial!: String"
             FROM contacts_table
-            "#
+            WHERE user_id = ?2
+            "#,
+            index_set,
+            user_id
         )
-        .fetch_one(&pool)
+        .fetch_all(pool)
         .await?;
-        let num_of_rows = num_of_rows.count as u32;
-        let page_size: u32 = 8;
-        let mut max_page = num_of_rows.div_ceil(page_size);
-        if max_page == 0 {
-            max_page = 1;
-        }
-        if page_set == 0 {
-            page_set = 1;
-        } else if page_set > max_page {
-            page_set = max_page;
+        let letters = initials
+            .into_iter()
+            .map(|row| {
+                let letter = index_letter(&row.initial).to_string();
+                (row.initial, letter)
+            })
+            .collect::<HashMap<String, String>>();
+        Ok(serde_json::to_string(&letters)?)
+    }
+    /// The A-Z (and "#") jump bar of the plain list, as `letter_index` does for
+    /// views in memory: the first position of each initial in the `sort_set`
+    /// order comes from SQL, grouped here by index letter.
+    pub async fn letter_pages(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        sort_set: &str,
+        index_by: IndexBy,
+    ) -> anyhow::Result<Vec<(char, Option<u32>)>> {
+        let sort_letters = match IndexBy::from_sort(sort_set) {
+            Some(sort_by) => Self::initial_letters(pool, user_id, sort_by).await?,
+            None => String::from("{}"),
         };
-        let offset = (page_set - 1) * page_size;
-
-        match (search_bar, birthday_set) {
-            ("", 0) => {
-                let contacts_set = sqlx::query_as!(
-                    Contact,
-                    r#"
-                    SELECT *
-                    FROM contacts_table
-                    ORDER BY birth_date
-                    LIMIT ?1 OFFSET ?2
-                    "#,
-                    page_size,
-                    offset
-                )
-                .fetch_all(&pool)
-                .await?;
-                let length = contacts_set.len() as u32;
-                Ok((
-                    Contacts {
-                        contacts: contacts_set,
-                    },
-                    length,
-                    page_set,
-                    max_page,
-                ))
-            }
-            ("", 1) => {
-                let contacts_set = sqlx::query_as!(
-                    Contact,
-                    r#"
-                    SELECT *
-                    FROM contacts_table
-                    WHERE SUBSTR(birth_date, 6) >= STRFTIME('%m-%d', DATE('now', 'localtime'))
-                    AND SUBSTR(birth_date, 6) < STRFTIME('%m-%d', DATE('now', 'localtime', '+1 MONTH'))
-                    ORDER BY STRFTIME('%m-%d', birth_date)
-                    LIMIT ?1 OFFSET ?2
-                    "#,
-                    page_size,
-                    offset
-                )
-                .fetch_all(&pool)
-                .await?;
-                let length = contacts_set.len() as u32;
-
-                Ok((
-                    Contacts {
-                        contacts: contacts_set,
-                    },
-                    length,
-                    page_set,
-                    max_page,
-                ))
-            }
-            (_, 0) => {
-                let contacts_set = sqlx::query_as!(
-                    Contact,
-                    r#"
-                    SELECT * FROM contacts_table
-                    WHERE (first_name LIKE '%' || ?1 || '%' 
-                    OR  last_name LIKE '%' || ?1 || '%'            
-                    OR phone LIKE '%' || ?1 || '%'
-                    OR email LIKE '%' || ?1 || '%'
-                    OR birth_date LIKE '%' || ?1 || '%'
-                    OR time_creation LIKE '%' || ?1 || '%' )
-                    ORDER BY id
-                    LIMIT ?2 OFFSET ?3
-                    "#,
-                    search_bar,
-                    page_size,
-                    offset
-                )
-                .fetch_all(&pool)
-                .await?;
-                let length = contacts_set.len() as u32;
-                Ok((
-                    Contacts {
-                        contacts: contacts_set,
-                    },
-                    length,
-                    page_set,
-                    max_page,
-                ))
-            }
-            (_, _) => {
-                let contacts_set = sqlx::query_as!(
-                    Contact,
-                    r#"
-                    SELECT * FROM contacts_table
-                    WHERE SUBSTR(birth_date, 6) >= STRFTIME('%m-%d', DATE('now', 'localtime'))
-                    AND SUBSTR(birth_date, 6) < STRFTIME('%m-%d', DATE('now', 'localtime', '+1 MONTH'))
-                    AND first_name LIKE '%' || ?1 || '%' 
-                    OR  last_name LIKE '%' || ?1 || '%'            
-                    OR phone LIKE '%' || ?1 || '%'
-                    OR email LIKE '%' || ?1 || '%'
-                    OR time_creation LIKE '%' || ?1 || '%'
-                    
-                    ORDER BY id
-                    LIMIT ?2 OFFSET ?3
-                    "#,
-                    search_bar,
-                    page_size,
-                    offset
-                )
-                .fetch_all(&pool)
-                .await?;
-                let length = contacts_set.len() as u32;
-                Ok((
-                    Contacts {
-                        contacts: contacts_set,
-                    },
-                    length,
-                    page_set,
-                    max_page,
-                ))
+        let index_set = index_by.sort();
+        // Same order as `search_contacts` on the full list.
+        let initials = sqlx::query!(
+            r#"
+            SELECT initial AS "initial!: String", MIN(position) AS "position!: i64"
+            FROM (
+                SELECT
+                    substr(trim(CASE ?3 WHEN 'last_name' THEN last_name ELSE first_name END), 1, 1)
+                    AS initial,
+                    ROW_NUMBER() OVER (
+                        ORDER BY
+                            bucket.value = '#',
+                            bucket.value,
+                            CASE ?2
+                                WHEN 'first_name' THEN trim(first_name)
+                                WHEN 'last_name' THEN trim(last_name)
+                                WHEN 'birth_date' THEN birth_date
+                                WHEN 'created' THEN time_creation
+                            END COLLATE NOCASE,
+                            CASE ?2
+                                WHEN 'first_name' THEN trim(last_name)
+                                WHEN 'last_name' THEN trim(first_name)
+                            END COLLATE NOCASE,
+                            CASE WHEN ?2 NOT IN ('first_name', 'last_name') THEN birth_date END,
+                            contacts_table.id
+                    ) - 1 AS position
+                FROM contacts_table
+                LEFT JOIN json_each(?4) AS bucket
+                    ON bucket.key = substr(trim(CASE ?2 WHEN 'last_name' THEN last_name ELSE first_name END), 1, 1)
+                WHERE user_id = ?1
+            )
+            GROUP BY initial
+            "#,
+            user_id,
+            sort_set,
+            index_set,
+            sort_letters
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut letters = ('A'..='Z')
+            .chain(['#'])
+            .map(|letter| (letter, None))
+            .collect::<Vec<(char, Option<u32>)>>();
+        for row in initials {
+            let letter = index_letter(&row.initial);
+            let first_page = row.position as u32 / PAGE_SIZE + 1;
+            if let Some((_, page)) = letters.iter_mut().find(|(l, _)| *l == letter) {
+                *page = Some(page.map_or(first_page, |page| page.min(first_page)));
             }
         }
+        Ok(letters)
+    }
+    /// Contacts owned by `user_id`, in id order, for archives.
+    pub async fn owned_contacts(
+        conn: &mut SqliteConnection,
+        user_id: i64,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<Contact>> {
+        let contacts_set = sqlx::query_as!(
+            Contact,
+            r#"
+            SELECT *
+            FROM contacts_table
+            WHERE user_id = ?1
+            ORDER BY id
+            LIMIT ?2 OFFSET ?3
+            "#,
+            user_id,
+            limit,
+            offset
+        )
+        .fetch_all(conn)
+        .await?;
+        Ok(contacts_set)
+    }
+    pub async fn count_owned(conn: &mut SqliteConnection, user_id: i64) -> anyhow::Result<u32> {
+        let result = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as count
+            FROM contacts_table
+            WHERE user_id = ?1
+            "#,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+        Ok(result.count as u32)
+    }
+    /// `user_id`'s contacts matching the search bar, in the requested order. A
+    /// negative `limit` returns every match.
+    pub async fn search_contacts(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        search_bar: &str,
+        sort_set: &str,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<Contact>> {
+        // Name sorts file under the A-Z index letters, so the index pages match.
+        let sort_letters = match IndexBy::from_sort(sort_set) {
+            Some(sort_by) => Self::initial_letters(pool, user_id, sort_by).await?,
+            None => String::from("{}"),
+        };
+        // An empty sort keeps the historical ordering of each view: birth date for
+        // the full list and id for searches.
+        let contacts_set = sqlx::query_as!(
+            Contact,
+            r#"
+            SELECT contacts_table.* FROM contacts_table
+            LEFT JOIN json_each(?6) AS bucket
+                ON bucket.key = substr(trim(CASE ?2 WHEN 'last_name' THEN last_name ELSE first_name END), 1, 1)
+            WHERE user_id = ?5
+            AND (?1 = ''
+                OR first_name LIKE '%' || ?1 || '%'
+                OR last_name LIKE '%' || ?1 || '%'
+                OR phone LIKE '%' || ?1 || '%'
+                OR email LIKE '%' || ?1 || '%'
+                OR birth_date LIKE '%' || ?1 || '%'
+                OR time_creation LIKE '%' || ?1 || '%')
+            ORDER BY
+                bucket.value = '#',
+                bucket.value,
+                CASE ?2
+                    WHEN 'first_name' THEN trim(first_name)
+                    WHEN 'last_name' THEN trim(last_name)
+                    WHEN 'birth_date' THEN birth_date
+                    WHEN 'created' THEN time_creation
+                END COLLATE NOCASE,
+                CASE ?2
+                    WHEN 'first_name' THEN trim(last_name)
+                    WHEN 'last_name' THEN trim(first_name)
+                END COLLATE NOCASE,
+                CASE WHEN ?1 = '' AND ?2 NOT IN ('first_name', 'last_name') THEN birth_date END,
+                contacts_table.id
+            LIMIT ?3 OFFSET ?4
+            "#,
+            search_bar,
+            sort_set,
+            limit,
+            offset,
+            user_id,
+            sort_letters
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(contacts_set)
+    }
+    /// Top `limit` contacts for autocomplete: names starting with the query
+    /// first, then emails starting with it. Prefix matches only, so each
+    /// keystroke stays on the name and email indexes; typo-tolerant matches
+    /// wait for the search to be submitted.
+    pub async fn suggest_contacts(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        query: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Contact>> {
+        let prefix = like_prefix(query);
+        // "John Sm" also looks for first names starting with John and last names
+        // starting with Sm.
+        let (first_prefix, last_prefix) = match query.split_once(char::is_whitespace) {
+            Some((first_name, last_name)) => {
+                (like_prefix(first_name), like_prefix(last_name.trim()))
+            }
+            None => (String::new(), String::new()),
+        };
+        let contacts_set = sqlx::query_as!(
+            Contact,
+            r#"
+            SELECT * FROM contacts_table
+            WHERE user_id = ?5
+            AND (first_name LIKE ?1 ESCAPE '\'
+                OR last_name LIKE ?1 ESCAPE '\'
+                OR email LIKE ?1 ESCAPE '\'
+                OR (first_name LIKE ?2 ESCAPE '\' AND last_name LIKE ?3 ESCAPE '\'))
+            ORDER BY
+                CASE
+                    WHEN email LIKE ?1 ESCAPE '\'
+                    AND first_name NOT LIKE ?1 ESCAPE '\'
+                    AND last_name NOT LIKE ?1 ESCAPE '\' THEN 1
+                    ELSE 0
+                END,
+                first_name COLLATE NOCASE,
+                last_name COLLATE NOCASE
+            LIMIT ?4
+            "#,
+            prefix,
+            first_prefix,
+            last_prefix,
+            limit,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(contacts_set)
     }
 }
 impl Contact {
-    pub async fn check_contact_errors(
-        &self,
-        pool: &Pool<Sqlite>,
-    ) -> anyhow::Result<Option<CreationErrorState>> {
-        let new_error = CreationErrorState {
+    /// Date of the next birthday on or after `today`. Feb 29 birthdays are
+    /// celebrated on Feb 28 in common years.
+    pub fn next_birthday(&self, today: NaiveDate) -> Option<NaiveDate> {
+        let birth = NaiveDate::parse_from_str(&self.birth_date, "%Y-%m-%d").ok()?;
+        let on_year = |year: i32| {
+            NaiveDate::from_ymd_opt(year, birth.month(), birth.day())
+                .or_else(|| NaiveDate::from_ymd_opt(year, 2, 28))
+        };
+        let this_year = on_year(today.year())?;
+        if this_year >= today {
+            Some(this_year)
+        } else {
+            on_year(today.year() + 1)
+        }
+    }
+    pub fn days_until_birthday(&self) -> Option<i64> {
+        let today = Local::now().date_naive();
+        Some((self.next_birthday(today)? - today).num_days())
+    }
+    pub fn birthday_countdown(&self) -> Option<String> {
+        match self.days_until_birthday()? {
+            0 => Some("today".to_owned()),
+            1 => Some("tomorrow".to_owned()),
+            days => Some(format!("in {} days", days)),
+        }
+    }
+    /// Age the contact turns on their next birthday.
+    pub fn turning_age(&self) -> Option<i32> {
+        let today = Local::now().date_naive();
+        let birth = NaiveDate::parse_from_str(&self.birth_date, "%Y-%m-%d").ok()?;
+        if birth.year() == NO_YEAR {
+            return None;
+        }
+        let age = self.next_birthday(today)?.year() - birth.year();
+        (age >= 0).then_some(age)
+    }
+    /// Sort key for name orders: index letter ('#' last), then the name, then
+    /// the other name, both compared like SQLite's NOCASE so lists sorted in
+    /// memory and by `search_contacts` agree.
+    pub fn index_key(&self, index_by: IndexBy) -> (bool, char, String, String, i64) {
+        let (name, other) = match index_by {
+            IndexBy::FirstName => (&self.first_name, &self.last_name),
+            IndexBy::LastName => (&self.last_name, &self.first_name),
+        };
+        let letter = index_letter(name);
+        (
+            letter == '#',
+            letter,
+            name.trim().to_ascii_lowercase(),
+            other.trim().to_ascii_lowercase(),
+            self.id,
+        )
+    }
+    /// Initials shown in place of a picture, e.g. "JM" for John Müller.
+    pub fn initials(&self) -> String {
+        [&self.first_name, &self.last_name]
+            .into_iter()
+            .filter_map(|name| name.trim().chars().next())
+            .flat_map(char::to_uppercase)
+            .collect()
+    }
+    /// Whether the search bar text appears in any field, ignoring ASCII case
+    /// like SQL's LIKE.
+    pub fn matches(&self, search_bar: &str) -> bool {
+        let search = search_bar.to_ascii_lowercase();
+        [
+            &self.first_name,
+            &self.last_name,
+            &self.phone,
+            &self.email,
+            &self.birth_date,
+            &self.time_creation,
+        ]
+        .into_iter()
+        .any(|field| field.to_ascii_lowercase().contains(&search))
+    }
+    /// Sum of the typo distances of each folded query word to the closest word in
+    /// the names and email, with those words in their original spelling. `None`
+    /// when any query word has no close match.
+    pub fn fuzzy_match(&self, query: &[String]) -> Option<(usize, String)> {
+        let words = [&self.first_name, &self.last_name, &self.email]
+            .into_iter()
+            .flat_map(|field| fold_words(field))
+            .collect::<Vec<(String, String)>>();
+        let mut total = 0;
+        let mut suggestion = Vec::new();
+        for query_word in query {
+            let (distance, original) = words
+                .iter()
+                .filter_map(|(original, folded)| {
+                    Some((word_distance(query_word, folded)?, original))
+                })
+                .min_by_key(|(distance, _)| *distance)?;
+            total += distance;
+            suggestion.push(original.as_str());
+        }
+        Some((total, suggestion.join(" ")))
+    }
+    /// The required-field checks of `check_contact_errors`, which need no
+    /// database.
+    pub fn required_errors(&self) -> CreationErrorState {
+        CreationErrorState {
             first_error: if self.first_name.is_empty() {
                 "First Name Required".to_string()
             } else {
@@ -288,58 +869,144 @@ impl Contact {
             } else {
                 "".to_string()
             },
-            email_unique_error: Self::validate_email(pool, self.email.as_str(), self.id as u32)
-                .await?,
+            email_unique_error: "".to_string(),
             birth_error: if self.birth_date.is_empty() {
                 "Birth Date Required".to_string()
             } else {
                 "".to_string()
             },
+        }
+    }
+    /// Runs on any executor, so imports can check against the rows their
+    /// transaction has written so far.
+    pub async fn check_contact_errors<'c, E>(
+        &self,
+        executor: E,
+    ) -> anyhow::Result<Option<CreationErrorState>>
+    where
+        E: Executor<'c, Database = Sqlite>,
+    {
+        let new_error = CreationErrorState {
+            email_unique_error: Self::validate_email(
+                executor,
+                self.email.as_str(),
+                self.id as u32,
+                self.user_id.unwrap_or_default(),
+            )
+            .await?,
+            ..self.required_errors()
         };
-        if new_error.first_error.is_empty()
-            && new_error.last_error.is_empty()
-            && new_error.phone_error.is_empty()
-            && new_error.email_error.is_empty()
-            && new_error.email_unique_error.is_empty()
-            && new_error.birth_error.is_empty()
-        {
+        if new_error.messages().is_empty() {
             Ok(None)
         } else {
             Ok(Some(new_error))
         }
     }
     pub async fn create_contact(&self, pool: Pool<Sqlite>) -> anyhow::Result<u32> {
-        let timestamp_str = get_time();
         let mut conn = pool.acquire().await?;
+        self.insert_contact(&mut conn).await
+    }
+    /// Inserts on an existing connection, so imports can run inside a transaction.
+    pub async fn insert_contact(&self, conn: &mut SqliteConnection) -> anyhow::Result<u32> {
+        let timestamp_str = get_time();
         let id_inserted = sqlx::query!(
             r#"
-            INSERT INTO contacts_table ( first_name, last_name, phone, email, birth_date, time_creation)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            INSERT INTO contacts_table ( first_name, last_name, phone, email, birth_date, time_creation, user_id, address, organization)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
             "#,
             self.first_name,
             self.last_name,
             self.phone,
             self.email,
             self.birth_date,
-            timestamp_str
+            timestamp_str,
+            self.user_id,
+            self.address,
+            self.organization
         )
-        .execute(&mut *conn)
+        .execute(conn)
         .await?
         .last_insert_rowid();
         Ok(id_inserted as u32)
     }
-    pub async fn edit_contact(&self, pool: Pool<Sqlite>) -> anyhow::Result<(u32, i64)> {
+    /// Updates the contact fields on an existing connection, keeping its creation time.
+    pub async fn update_contact(&self, conn: &mut SqliteConnection) -> anyhow::Result<u32> {
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE contacts_table
+            SET first_name = ?1,
+                last_name = ?2,
+                phone = ?3,
+                email = ?4,
+                birth_date = ?5,
+                address = ?7,
+                organization = ?8
+            WHERE id = ?6
+            "#,
+            self.first_name,
+            self.last_name,
+            self.phone,
+            self.email,
+            self.birth_date,
+            self.id,
+            self.address,
+            self.organization,
+        )
+        .execute(conn)
+        .await?
+        .rows_affected();
+        Ok(rows_affected as u32)
+    }
+    /// The first of `user_id`'s contacts with this email, ignoring ASCII case
+    /// like `validate_email`.
+    pub async fn find_by_email(
+        conn: &mut SqliteConnection,
+        email: &str,
+        user_id: i64,
+    ) -> anyhow::Result<Option<i64>> {
+        let id_found = sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM contacts_table
+            WHERE email = ?1 COLLATE NOCASE AND user_id = ?2
+            ORDER BY id
+            LIMIT 1
+            "#,
+            email,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+        Ok(id_found)
+    }
+    /// Contact `id` if it belongs to `user_id`.
+    pub async fn get_contact(
+        pool: &Pool<Sqlite>,
+        id: u32,
+        user_id: i64,
+    ) -> anyhow::Result<Option<Contact>> {
         let contact_set = sqlx::query_as!(
             Contact,
             r#"
             SELECT *
             FROM contacts_table
-            WHERE id = ?
+            WHERE id = ?1 AND user_id = ?2
             "#,
-            self.id
+            id,
+            user_id
         )
-        .fetch_one(&pool)
+        .fetch_optional(pool)
         .await?;
+        Ok(contact_set)
+    }
+    pub async fn edit_contact(
+        &self,
+        pool: Pool<Sqlite>,
+        user_id: i64,
+    ) -> anyhow::Result<(u32, i64)> {
+        let Some(contact_set) = Contact::get_contact(&pool, self.id as u32, user_id).await? else {
+            return Ok((0, self.id));
+        };
 
         let rows_affected = sqlx::query!(
             r#"
@@ -349,8 +1016,10 @@ impl Contact {
                 phone = ?3,
                 email = ?4,
                 birth_date = ?5,
-                time_creation = ?6
-            WHERE id = ?7                
+                time_creation = ?6,
+                address = ?8,
+                organization = ?9
+            WHERE id = ?7 AND user_id = ?10
             "#,
             self.first_name,
             self.last_name,
@@ -359,28 +1028,37 @@ impl Contact {
             self.birth_date,
             contact_set.time_creation,
             self.id,
+            self.address,
+            self.organization,
+            user_id,
         )
         .execute(&pool)
         .await?
         .rows_affected();
         Ok((rows_affected as u32, self.id))
     }
-    pub async fn validate_email(
-        pool: &Pool<Sqlite>,
+    /// Emails are unique among one user's contacts, ignoring ASCII case.
+    pub async fn validate_email<'c, E>(
+        executor: E,
         email_set: &str,
         id: u32,
-    ) -> anyhow::Result<String> {
+        user_id: i64,
+    ) -> anyhow::Result<String>
+    where
+        E: Executor<'c, Database = Sqlite>,
+    {
         let email_equal;
         match id {
             0 => {
                 let result = sqlx::query!(
                     r#"
                         SELECT COUNT(*) as count FROM contacts_table
-                        WHERE email = ?1
+                        WHERE email = ?1 COLLATE NOCASE AND user_id = ?2
                         "#,
-                    email_set
+                    email_set,
+                    user_id
                 )
-                .fetch_one(pool)
+                .fetch_one(executor)
                 .await?;
                 email_equal = result.count;
             }
@@ -388,12 +1066,13 @@ impl Contact {
                 let rec = sqlx::query!(
                     r#"
                         SELECT COUNT(*) as count FROM contacts_table
-                        WHERE email = ?1 AND NOT id = ?2 
+                        WHERE email = ?1 COLLATE NOCASE AND NOT id = ?2 AND user_id = ?3
                         "#,
                     email_set,
-                    x
+                    x,
+                    user_id
                 )
-                .fetch_one(pool)
+                .fetch_one(executor)
                 .await?;
                 email_equal = rec.count;
             }
@@ -407,63 +1086,4671 @@ impl Contact {
 
 // endregion: CONTACTS
 
-// region: ARCHIVER
+// region: VCARD
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 
-#[derive(Clone, Deserialize, FromRef)]
-pub struct ArchiverState {
-    pub archive_status: String,
-    pub archive_progress: f64,
+/// Year stored for birthdays given without one ("--0415"), as Apple Contacts
+/// does. 1604 is a leap year, so Feb 29 survives.
+pub const NO_YEAR: i32 = 1604;
+
+/// One unfolded content line, e.g. `item1.TEL;TYPE=cell,pref:+1 555 0100`.
+#[derive(Debug, Clone, Default)]
+pub struct VCardProperty {
+    /// Uppercase name without the group prefix.
+    pub name: String,
+    /// Uppercase parameter names with their values; bare vCard 2.1 parameters
+    /// such as `HOME` are read as `TYPE=HOME`.
+    pub params: Vec<(String, String)>,
+    /// Still escaped, but with quoted-printable already decoded.
+    pub value: String,
 }
-impl Default for ArchiverState {
-    fn default() -> Self {
-        ArchiverState {
-            archive_status: "Waiting".to_owned(),
-            archive_progress: 0.0,
+
+impl VCardProperty {
+    /// Splits a line at its first unquoted ':' and its parameters at unquoted ';'.
+    fn parse(line: &str) -> Option<VCardProperty> {
+        let mut in_quotes = false;
+        let mut parts = vec![String::new()];
+        let mut value = None;
+        for (index, c) in line.char_indices() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                ';' if !in_quotes => parts.push(String::new()),
+                ':' if !in_quotes => {
+                    value = Some(&line[index + 1..]);
+                    break;
+                }
+                _ => parts.last_mut()?.push(c),
+            }
+        }
+        let value = value?;
+        let mut parts = parts.into_iter();
+        let name = parts.next()?;
+        let name = name.rsplit('.').next()?.trim().to_uppercase();
+        let params = parts
+            .map(|param| match param.split_once('=') {
+                Some((key, val)) => (key.trim().to_uppercase(), val.trim().to_owned()),
+                None => ("TYPE".to_owned(), param.trim().to_owned()),
+            })
+            .collect::<Vec<(String, String)>>();
+        let mut property = VCardProperty {
+            name,
+            params,
+            value: value.to_owned(),
+        };
+        if property.has_param("ENCODING", "QUOTED-PRINTABLE") {
+            let latin1 = property.has_param("CHARSET", "ISO-8859-1");
+            property.value = decode_quoted_printable(&property.value, latin1);
         }
+        Some(property)
     }
-}
-impl ArchiverState {
-    pub fn status(&self) -> String {
-        self.archive_status.clone()
+    /// Every comma separated value of the parameter, e.g. `TYPE=home,pref`.
+    fn param_values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.params
+            .iter()
+            .filter(move |(name, _)| name == key)
+            .flat_map(|(_, values)| values.split(','))
+            .map(|value| value.trim_matches('"').trim())
     }
-    pub fn progress(&self) -> f64 {
-        self.archive_progress
+    fn has_param(&self, key: &str, value: &str) -> bool {
+        self.param_values(key)
+            .any(|param| param.eq_ignore_ascii_case(value))
+    }
+    /// Lower is preferred: vCard 4.0 `PREF=1..100`, vCard 3.0 `TYPE=pref`.
+    fn pref_rank(&self) -> u32 {
+        if let Some(pref) = self.param_values("PREF").find_map(|pref| pref.parse().ok()) {
+            return pref;
+        }
+        match self.has_param("TYPE", "pref") {
+            true => 1,
+            false => 101,
+        }
     }
-    pub fn archive_file(&self) -> &str {
-        //"/db/contacts.db"
-        "D:/RustProjects/axum-3-htmx/db/contacts.db"
+    fn text(&self) -> String {
+        unescape_vcard(&self.value)
     }
-}
-pub async fn run_thread(archiver_state: ArchiverStateType) {
-    for i in 0..10 {
-        let random = rand::thread_rng().gen::<f64>();
-        let sleep_time = (1000.0 * random) as u64;
-        sleep(Duration::from_millis(sleep_time)).await;
-        archiver_state.write().await.archive_progress = ((i as f64) + 1.0) / 10.0;
-        //if state.read().await.archiver_state.archive_status != "Running" {
-        //    return;
-        //}
+    /// Structured values such as `N:Family;Given;;;`.
+    fn components(&self) -> Vec<String> {
+        let mut components = vec![String::new()];
+        let mut chars = self.value.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    components.last_mut().unwrap().push(c);
+                    if let Some(escaped) = chars.next() {
+                        components.last_mut().unwrap().push(escaped);
+                    }
+                }
+                ';' => components.push(String::new()),
+                _ => components.last_mut().unwrap().push(c),
+            }
+        }
+        components.iter().map(|c| unescape_vcard(c)).collect()
     }
-    archiver_state.write().await.archive_status = "Complete".to_owned();
 }
 
-// endregion: ARCHIVER
+fn unescape_vcard(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(escaped) => unescaped.push(escaped),
+                None => unescaped.push('\\'),
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
 
-// region: USERS
+fn decode_quoted_printable(value: &str, latin1: bool) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'=', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    match latin1 {
+        true => decoded.into_iter().map(char::from).collect(),
+        false => String::from_utf8_lossy(&decoded).into_owned(),
+    }
+}
 
-use axum::async_trait;
-use axum_login::{AuthUser, AuthnBackend, UserId};
-use password_auth::verify_password;
-use sqlx::SqlitePool;
-use tokio::task;
+/// Joins folded lines (a leading space or tab continues the previous line) and
+/// quoted-printable soft line breaks (a trailing '=').
+fn unfold_vcard(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in text.split('\n') {
+        let line = raw.strip_suffix('\r').unwrap_or(raw);
+        match lines.last_mut() {
+            Some(previous) if is_soft_break(previous) => {
+                previous.pop();
+                previous.push_str(line);
+            }
+            Some(previous) if line.starts_with([' ', '\t']) => previous.push_str(&line[1..]),
+            _ if line.trim().is_empty() => {}
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    lines
+}
 
-use crate::routers::CredentialsParam;
+fn is_soft_break(line: &str) -> bool {
+    let header = line.split(':').next().unwrap_or("");
+    line.ends_with('=') && header.to_uppercase().contains("QUOTED-PRINTABLE")
+}
+
+/// Reads `BDAY` values: `1990-04-15`, `19900415`, with or without a time, and
+/// the year-less `--0415` / `--04-15`.
+pub fn parse_vcard_date(value: &str) -> Option<NaiveDate> {
+    let date = value.trim().split('T').next()?.replace('-', "");
+    let (year, month_day) = match value.trim().starts_with("--") {
+        true => (NO_YEAR, date.as_str()),
+        false => (date.get(..4)?.parse().ok()?, date.get(4..)?),
+    };
+    if month_day.len() != 4 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(
+        year,
+        month_day[..2].parse().ok()?,
+        month_day[2..].parse().ok()?,
+    )
+}
+
+/// A card read from a `.vcf` file, with every phone and email kept in order
+/// of preference.
+#[derive(Debug, Clone, Default)]
+pub struct VCard {
+    pub version: String,
+    pub formatted_name: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+    pub birth_date: String,
+    /// Postal addresses as envelope lines, preferred first.
+    pub addresses: Vec<String>,
+    pub organization: String,
+    /// A `data:` URI or a URL.
+    pub photo: Option<String>,
+    /// Things that could not be read, shown next to the card in previews.
+    pub warnings: Vec<String>,
+}
+
+impl VCard {
+    /// Every `BEGIN:VCARD` .. `END:VCARD` block of a file, versions 2.1 to 4.0.
+    pub fn parse_all(text: &str) -> Vec<VCard> {
+        let mut cards = Vec::new();
+        let mut current: Option<Vec<VCardProperty>> = None;
+        for line in unfold_vcard(text.trim_start_matches('\u{feff}')) {
+            let Some(property) = VCardProperty::parse(&line) else {
+                continue;
+            };
+            match (
+                property.name.as_str(),
+                property.text().to_uppercase().as_str(),
+            ) {
+                ("BEGIN", "VCARD") => current = Some(Vec::new()),
+                ("END", "VCARD") => {
+                    if let Some(properties) = current.take() {
+                        cards.push(VCard::from_properties(properties));
+                    }
+                }
+                _ => {
+                    if let Some(properties) = current.as_mut() {
+                        properties.push(property);
+                    }
+                }
+            }
+        }
+        cards
+    }
+    fn from_properties(mut properties: Vec<VCardProperty>) -> VCard {
+        let mut card = VCard::default();
+        properties.sort_by_key(VCardProperty::pref_rank);
+        for property in &properties {
+            match property.name.as_str() {
+                "VERSION" => card.version = property.text(),
+                "FN" => card.formatted_name = property.text().trim().to_owned(),
+                "N" => {
+                    let components = property.components();
+                    card.last_name = components.first().cloned().unwrap_or_default();
+                    card.first_name = components
+                        .iter()
+                        .skip(1)
+                        .take(2)
+                        .filter(|name| !name.is_empty())
+                        .cloned()
+                        .collect::<Vec<String>>()
+                        .join(" ");
+                }
+                "TEL" => {
+                    let phone = property.text();
+                    let phone = phone.trim().trim_start_matches("tel:").to_owned();
+                    if !phone.is_empty() {
+                        card.phones.push(phone);
+                    }
+                }
+                "EMAIL" => {
+                    let email = property.text();
+                    let email = email.trim().trim_start_matches("mailto:").to_owned();
+                    if !email.is_empty() {
+                        card.emails.push(email);
+                    }
+                }
+                "BDAY" => match parse_vcard_date(&property.text()) {
+                    Some(date) => card.birth_date = date.format("%Y-%m-%d").to_string(),
+                    None => card
+                        .warnings
+                        .push(format!("Birthday \"{}\" not understood", property.text())),
+                },
+                "ADR" => {
+                    let address = adr_lines(&property.components());
+                    if !address.is_empty() {
+                        card.addresses.push(address);
+                    }
+                }
+                "ORG" if card.organization.is_empty() => {
+                    let units = property.components();
+                    card.organization = units
+                        .iter()
+                        .map(|unit| unit.trim())
+                        .filter(|unit| !unit.is_empty())
+                        .collect::<Vec<&str>>()
+                        .join(", ");
+                }
+                "PHOTO" => card.photo = Self::photo(property),
+                _ => {}
+            }
+        }
+        if !["2.1", "3.0", "4.0"].contains(&card.version.as_str()) {
+            card.warnings
+                .push(format!("Unknown vCard version \"{}\"", card.version));
+        }
+        if card.first_name.is_empty() && card.last_name.is_empty() {
+            match card.formatted_name.rsplit_once(' ') {
+                Some((first, last)) => {
+                    card.first_name = first.trim().to_owned();
+                    card.last_name = last.trim().to_owned();
+                }
+                None => card.first_name = card.formatted_name.clone(),
+            }
+        }
+        card
+    }
+    fn photo(property: &VCardProperty) -> Option<String> {
+        let value = property.value.split_whitespace().collect::<String>();
+        if property.has_param("ENCODING", "b") || property.has_param("ENCODING", "BASE64") {
+            let subtype = property
+                .param_values("TYPE")
+                .find(|kind| !kind.contains('/'))
+                .unwrap_or("jpeg")
+                .to_lowercase();
+            // Only keep data that really is base64, the rest is not worth a warning.
+            BASE64.decode(&value).ok()?;
+            return Some(format!("data:image/{};base64,{}", subtype, value));
+        }
+        match value.starts_with("data:image/")
+            || value.starts_with("http://")
+            || value.starts_with("https://")
+        {
+            true => Some(value),
+            false => None,
+        }
+    }
+    /// The contact this card imports as: the preferred phone and email win.
+    pub fn to_contact(&self) -> Contact {
+        Contact {
+            first_name: self.first_name.trim().to_owned(),
+            last_name: self.last_name.trim().to_owned(),
+            phone: self.phones.first().cloned().unwrap_or_default(),
+            email: self.emails.first().cloned().unwrap_or_default(),
+            birth_date: self.birth_date.clone(),
+            address: self.addresses.first().cloned().unwrap_or_default(),
+            organization: self.organization.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// An `ADR` value (PO box; extended; street; locality; region; postal code;
+/// country) laid out as envelope lines.
+fn adr_lines(components: &[String]) -> String {
+    let part = |index: usize| {
+        components
+            .get(index)
+            .map(|value| value.trim())
+            .unwrap_or_default()
+    };
+    let town = [part(3), part(4), part(5)]
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    [part(0), part(1), part(2), town.as_str(), part(6)]
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Escapes a vCard text value: backslashes, commas, semicolons and newlines.
+pub fn escape_vcard(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ',' => escaped.push_str("\\,"),
+            ';' => escaped.push_str("\\;"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Folds a content line at 75 octets, never inside a UTF-8 character, and ends
+/// it with CRLF.
+pub fn fold_vcard_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+impl Contact {
+    /// This contact as a vCard 4.0, ready for phone address books.
+    pub fn to_vcard(&self) -> String {
+        let first_name = self.first_name.trim();
+        let last_name = self.last_name.trim();
+        let mut lines = vec![
+            "BEGIN:VCARD".to_owned(),
+            "VERSION:4.0".to_owned(),
+            format!(
+                "FN:{}",
+                escape_vcard(format!("{} {}", first_name, last_name).trim())
+            ),
+            format!(
+                "N:{};{};;;",
+                escape_vcard(last_name),
+                escape_vcard(first_name)
+            ),
+        ];
+        // A tel: URI only allows digits, '+' and visual separators.
+        let phone = self
+            .phone
+            .trim()
+            .chars()
+            .map(|c| if c.is_whitespace() { '-' } else { c })
+            .filter(|c| c.is_ascii_digit() || "+-.()*#".contains(*c))
+            .collect::<String>();
+        if !phone.is_empty() {
+            lines.push(format!("TEL;VALUE=uri:tel:{}", phone));
+        }
+        if !self.email.trim().is_empty() {
+            lines.push(format!("EMAIL:{}", escape_vcard(self.email.trim())));
+        }
+        if let Ok(birth) = NaiveDate::parse_from_str(&self.birth_date, "%Y-%m-%d") {
+            match birth.year() {
+                NO_YEAR => lines.push(format!("BDAY:{}", birth.format("--%m%d"))),
+                _ => lines.push(format!("BDAY:{}", birth.format("%Y%m%d"))),
+            }
+        }
+        if !self.organization.trim().is_empty() {
+            lines.push(format!("ORG:{}", escape_vcard(self.organization.trim())));
+        }
+        // The address is free text, so all of it goes in the street component.
+        if !self.address.trim().is_empty() {
+            lines.push(format!("ADR:;;{};;;;", escape_vcard(self.address.trim())));
+        }
+        lines.push("END:VCARD".to_owned());
+        lines.iter().map(|line| fold_vcard_line(line)).collect()
+    }
+}
+
+/// File name for a contact's vCard, e.g. `john-muller.vcf`.
+pub fn vcard_file_name(contact: &Contact) -> String {
+    let name = fold_words(&format!("{} {}", contact.first_name, contact.last_name))
+        .into_iter()
+        .map(|(_, folded)| folded)
+        .collect::<Vec<String>>()
+        .join("-");
+    match name.is_empty() {
+        true => format!("contact-{}.vcf", contact.id),
+        false => format!("{}.vcf", name),
+    }
+}
+
+#[cfg(test)]
+mod vcard_tests {
+    use super::*;
+
+    #[test]
+    fn parse_all_unfolds_and_unescapes() {
+        let text = "\u{feff}BEGIN:VCARD\r\n\
+            VERSION:3.0\r\n\
+            N:Müller;John;;;\r\n\
+            FN:John Müller\r\n\
+            TEL:+1 555\r\n 0100\r\n\
+            EMAIL;TYPE=work:j@work.io\r\n\
+            EMAIL;TYPE=home,pref:john@home.io\r\n\
+            ORG:Acme\\; Sons;Sales\r\n\
+            ADR;TYPE=home:;;12 Main St\\, Apt 4;Springfield;;;\r\n\
+            END:VCARD\r\n";
+        let cards = VCard::parse_all(text);
+        assert_eq!(cards.len(), 1);
+        let card = &cards[0];
+        assert_eq!(card.first_name, "John");
+        assert_eq!(card.last_name, "Müller");
+        assert_eq!(card.phones, ["+1 5550100"]);
+        assert_eq!(card.emails, ["john@home.io", "j@work.io"]);
+        assert_eq!(card.organization, "Acme; Sons, Sales");
+        assert!(card.addresses[0].contains("12 Main St, Apt 4"));
+        assert!(card.warnings.is_empty());
+    }
+
+    #[test]
+    fn parse_all_reads_quoted_printable_soft_breaks() {
+        let text = "BEGIN:VCARD\n\
+            VERSION:2.1\n\
+            N;ENCODING=QUOTED-PRINTABLE;CHARSET=UTF-8:M=C3=BCl=\n\
+            ler;Anna\n\
+            TEL;CELL:555 0100\n\
+            END:VCARD\n";
+        let cards = VCard::parse_all(text);
+        assert_eq!(cards[0].last_name, "Müller");
+        assert_eq!(cards[0].first_name, "Anna");
+        assert_eq!(cards[0].phones, ["555 0100"]);
+    }
+
+    #[test]
+    fn parse_all_keeps_every_card_and_reports_bad_birthdays() {
+        let text = "BEGIN:VCARD\nVERSION:4.0\nFN:Ada Lovelace\nBDAY:--1210\nEND:VCARD\n\
+            stray line\n\
+            BEGIN:VCARD\nVERSION:4.0\nFN:Bob\nBDAY:someday\nEND:VCARD\n";
+        let cards = VCard::parse_all(text);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].first_name, "Ada");
+        assert_eq!(cards[0].last_name, "Lovelace");
+        assert_eq!(cards[0].birth_date, format!("{}-12-10", NO_YEAR));
+        assert_eq!(cards[1].first_name, "Bob");
+        assert_eq!(cards[1].warnings.len(), 1);
+    }
+}
+
+// endregion: VCARD
+
+// region: EXPORT
+
+/// Columns `/contacts/export` can emit, in their default order.
+pub const EXPORT_COLUMNS: [&str; 9] = [
+    "id",
+    "first_name",
+    "last_name",
+    "phone",
+    "email",
+    "birth_date",
+    "address",
+    "organization",
+    "time_creation",
+];
+
+/// Contacts fetched per query while streaming an export.
+pub const EXPORT_CHUNK: i64 = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+    /// Google Contacts CSV.
+    Google,
+    /// Outlook CSV.
+    Outlook,
+    Ldif,
+}
+
+impl Contact {
+    pub fn column(&self, name: &str) -> String {
+        match name {
+            "id" => self.id.to_string(),
+            "first_name" => self.first_name.clone(),
+            "last_name" => self.last_name.clone(),
+            "phone" => self.phone.clone(),
+            "email" => self.email.clone(),
+            "birth_date" => self.birth_date_display(),
+            "address" => self.address.clone(),
+            "organization" => self.organization.clone(),
+            "time_creation" => self.time_creation.clone(),
+            _ => String::new(),
+        }
+    }
+    pub fn to_json(&self, columns: &[&str]) -> serde_json::Value {
+        let object = columns
+            .iter()
+            .map(|name| match *name {
+                "id" => (name.to_string(), serde_json::Value::from(self.id)),
+                _ => (name.to_string(), serde_json::Value::from(self.column(name))),
+            })
+            .collect::<serde_json::Map<String, serde_json::Value>>();
+        serde_json::Value::Object(object)
+    }
+}
+
+/// Keeps the requested columns that exist, in the order asked; all of them
+/// when none are.
+pub fn export_columns(requested: &[String]) -> Vec<&'static str> {
+    let columns = requested
+        .iter()
+        .filter_map(|name| {
+            EXPORT_COLUMNS
+                .iter()
+                .find(|column| **column == name.as_str())
+        })
+        .copied()
+        .collect::<Vec<&str>>();
+    match columns.is_empty() {
+        true => EXPORT_COLUMNS.to_vec(),
+        false => columns,
+    }
+}
+
+/// RFC 4180 CSV: CRLF line breaks, fields quoted only when needed, quotes
+/// doubled. The header row is written with the first chunk.
+pub fn export_csv(
+    contacts_set: &[Contact],
+    columns: &[&str],
+    header: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(Vec::new());
+    if header {
+        writer.write_record(columns)?;
+    }
+    for contact in contacts_set {
+        writer.write_record(columns.iter().map(|name| contact.column(name)))?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// One chunk of the JSON array; `first` decides whether it needs a leading comma.
+pub fn export_json(contacts_set: &[Contact], columns: &[&str], first: bool) -> Vec<u8> {
+    let objects = contacts_set
+        .iter()
+        .map(|contact| contact.to_json(columns).to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+    match first {
+        true => objects.into_bytes(),
+        false => format!(",{}", objects).into_bytes(),
+    }
+}
+
+// endregion: EXPORT
+
+// region: IMPORT
+
+/// One parsed record of an import file, as shown in the preview.
+#[derive(Debug, Clone, Default)]
+pub struct ImportRow {
+    pub contact: Contact,
+    /// Details the contact cannot hold, e.g. extra phones.
+    pub notes: Vec<String>,
+    pub photo: Option<String>,
+    /// Validation and parsing errors; rows with any are not imported.
+    pub errors: Vec<String>,
+}
+
+impl ImportRow {
+    pub fn from_vcard(card: &VCard) -> ImportRow {
+        let mut notes = Vec::new();
+        if card.phones.len() > 1 {
+            notes.push(format!("Other phones: {}", card.phones[1..].join(", ")));
+        }
+        if card.emails.len() > 1 {
+            notes.push(format!("Other emails: {}", card.emails[1..].join(", ")));
+        }
+        if card.photo.is_some() {
+            notes.push("Photo is not imported".to_owned());
+        }
+        ImportRow {
+            contact: card.to_contact(),
+            notes,
+            photo: card.photo.clone(),
+            errors: card.warnings.clone(),
+        }
+    }
+    /// Runs `check_contact_errors` against `user_id`'s contacts on every row and
+    /// flags emails repeated within the file itself.
+    pub async fn validate_all(
+        rows: &mut [ImportRow],
+        user_id: i64,
+        pool: &Pool<Sqlite>,
+    ) -> anyhow::Result<()> {
+        let mut seen_emails = HashSet::new();
+        for row in rows.iter_mut() {
+            row.contact.user_id = Some(user_id);
+            if let Some(errors) = row.contact.check_contact_errors(pool).await? {
+                row.errors.extend(errors.messages());
+            }
+            let email = row.contact.email.to_lowercase();
+            if !email.is_empty() && !seen_emails.insert(email) {
+                row.errors.push("Email repeated in this file".to_owned());
+            }
+        }
+        Ok(())
+    }
+    /// Inserts the rows without errors in a single transaction, owned by
+    /// `user_id`; returns how many.
+    pub async fn import_valid(
+        rows: &[ImportRow],
+        user_id: i64,
+        pool: &Pool<Sqlite>,
+    ) -> anyhow::Result<u32> {
+        let mut tx = pool.begin().await?;
+        let mut inserted = 0;
+        for row in rows.iter().filter(|row| row.errors.is_empty()) {
+            let contact = Contact {
+                user_id: Some(user_id),
+                ..row.contact.clone()
+            };
+            contact.insert_contact(&mut tx).await?;
+            inserted += 1;
+        }
+        tx.commit().await?;
+        Ok(inserted)
+    }
+}
+
+/// Where uploaded files wait between their preview and its confirmation:
+/// `UPLOAD_DIR`, or `db/uploads` next to the database.
+pub fn upload_dir() -> PathBuf {
+    std::env::var("UPLOAD_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("db/uploads"))
+}
+
+/// Removes the files in `dir` last written more than `seconds` ago.
+pub async fn remove_expired(dir: &Path, seconds: u64) -> anyhow::Result<()> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let expired = entry
+            .metadata()
+            .await?
+            .modified()?
+            .elapsed()
+            .is_ok_and(|age| age.as_secs() > seconds);
+        if expired {
+            let _ = tokio::fs::remove_file(entry.path()).await;
+        }
+    }
+    Ok(())
+}
+
+/// How long an upload waits for its preview to be confirmed, in seconds.
+pub const UPLOAD_SECONDS: u64 = 3600;
+
+/// Keeps an uploaded file on the server while its preview is shown, so forms
+/// only carry the returned id. Uploads older than `UPLOAD_SECONDS` go first.
+pub async fn stash_upload(bytes: &[u8]) -> anyhow::Result<String> {
+    let dir = upload_dir();
+    tokio::fs::create_dir_all(&dir).await?;
+    remove_expired(&dir, UPLOAD_SECONDS).await?;
+    let id = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 24]>());
+    tokio::fs::write(dir.join(&id), bytes).await?;
+    Ok(id)
+}
+
+/// Path of the upload stashed under `id`. Ids are URL-safe base64, which keeps
+/// them inside `upload_dir()`.
+fn upload_path(id: &str) -> Option<PathBuf> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| upload_dir().join(id))
+}
+
+/// The upload stashed under `id`, or `None` once it expired or was used.
+pub async fn read_upload(id: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(path) = upload_path(id) else {
+        return Ok(None);
+    };
+    let expired = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata
+            .modified()?
+            .elapsed()
+            .is_ok_and(|age| age.as_secs() > UPLOAD_SECONDS),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    if expired {
+        remove_upload(id).await?;
+        return Ok(None);
+    }
+    Ok(Some(tokio::fs::read(&path).await?))
+}
+
+/// Removes the upload stashed under `id` once it has been used.
+pub async fn remove_upload(id: &str) -> anyhow::Result<()> {
+    if let Some(path) = upload_path(id) {
+        match tokio::fs::remove_file(path).await {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// endregion: IMPORT
+
+// region: CSV IMPORT
+
+use encoding_rs::{Encoding, UTF_8};
+use std::collections::HashMap;
+
+/// Contact fields a CSV column can be mapped to, with the (folded) headers
+/// that are mapped to them automatically.
+pub const CSV_FIELDS: [(&str, &str, &[&str]); 7] = [
+    (
+        "first_name",
+        "First Name",
+        &[
+            "first name",
+            "firstname",
+            "given name",
+            "givenname",
+            "first",
+            "forename",
+        ],
+    ),
+    (
+        "last_name",
+        "Last Name",
+        &[
+            "last name",
+            "lastname",
+            "surname",
+            "family name",
+            "sn",
+            "last",
+        ],
+    ),
+    (
+        "phone",
+        "Phone",
+        &[
+            "phone",
+            "phone number",
+            "telephone",
+            "tel",
+            "mobile",
+            "cell",
+            "phone 1 value",
+            "mobile phone",
+            "primary phone",
+            "home phone",
+            "business phone",
+        ],
+    ),
+    (
+        "email",
+        "Email",
+        &[
+            "email",
+            "e mail",
+            "email address",
+            "mail",
+            "e mail 1 value",
+            "e mail address",
+        ],
+    ),
+    (
+        "birth_date",
+        "Birth Date",
+        &[
+            "birth date",
+            "birthday",
+            "birthdate",
+            "date of birth",
+            "dob",
+        ],
+    ),
+    (
+        "address",
+        "Address",
+        &[
+            "address",
+            "street address",
+            "postal address",
+            "home address",
+            "address 1 formatted",
+            "home street",
+            "business street",
+            "street",
+        ],
+    ),
+    (
+        "organization",
+        "Organization",
+        &[
+            "organization",
+            "organisation",
+            "organization name",
+            "organization 1 name",
+            "company",
+            "company name",
+            "employer",
+        ],
+    ),
+];
+
+/// How many rows the dry run shows before the real import.
+pub const CSV_PREVIEW_ROWS: usize = 20;
+
+/// Decodes an uploaded file to UTF-8: a BOM wins, then valid UTF-8, then the
+/// best guess of `chardetng` (usually windows-1252 for spreadsheet exports).
+/// Returns the text and the name of the encoding used.
+pub fn decode_csv(bytes: &[u8]) -> (String, &'static str) {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return (text.into_owned(), encoding.name());
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return (text.to_owned(), UTF_8.name());
+    }
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, true);
+    let (text, _, _) = encoding.decode(bytes);
+    (text.into_owned(), encoding.name())
+}
+
+/// Picks the delimiter that splits the first lines into the same, largest
+/// number of fields. Ties go to the comma.
+pub fn detect_delimiter(text: &str) -> u8 {
+    let lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(10)
+        .collect::<Vec<&str>>();
+    let count = |line: &str, delimiter: u8| {
+        let mut quoted = false;
+        line.bytes()
+            .filter(|byte| {
+                if *byte == b'"' {
+                    quoted = !quoted;
+                }
+                !quoted && *byte == delimiter
+            })
+            .count()
+    };
+    [b'|', b'\t', b';', b',']
+        .into_iter()
+        .max_by_key(|delimiter| {
+            let counts = lines
+                .iter()
+                .map(|line| count(line, *delimiter))
+                .collect::<Vec<usize>>();
+            let first = counts.first().copied().unwrap_or(0);
+            let consistent = counts.iter().filter(|count| **count == first).count();
+            (first > 0, consistent, first)
+        })
+        .unwrap_or(b',')
+}
+
+pub fn delimiter_name(delimiter: u8) -> String {
+    match delimiter {
+        b'\t' => "Tab".to_owned(),
+        b',' => "Comma".to_owned(),
+        b';' => "Semicolon".to_owned(),
+        b'|' => "Pipe".to_owned(),
+        other => (other as char).to_string(),
+    }
+}
+
+/// Splits the text into its header row and data rows, dropping blank rows.
+pub fn read_csv(text: &str, delimiter: u8) -> anyhow::Result<(Vec<String>, Vec<Vec<String>>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record?
+            .iter()
+            .map(|field| field.trim().to_owned())
+            .collect::<Vec<String>>();
+        if record.iter().any(|field| !field.is_empty()) {
+            records.push(record);
+        }
+    }
+    match records.is_empty() {
+        true => Ok((Vec::new(), Vec::new())),
+        false => {
+            let headers = records.remove(0);
+            Ok((headers, records))
+        }
+    }
+}
+
+/// Accepts ISO dates, the vCard forms (`19800415`, `--0415`) and `15.04.1980`;
+/// Outlook's `4/15/1980` only in Outlook files, since elsewhere `4/5/1980`
+/// may well mean May 4.
+pub fn parse_csv_date(value: &str, format: ContactFileFormat) -> Option<NaiveDate> {
+    let us_format = match format {
+        ContactFileFormat::OutlookCsv => Some("%m/%d/%Y"),
+        _ => None,
+    };
+    ["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%Y"]
+        .into_iter()
+        .chain(us_format)
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .or_else(|| match value.is_ascii() {
+            true => parse_vcard_date(value),
+            false => None,
+        })
+}
+
+/// Which CSV column feeds each of `CSV_FIELDS`, in the same order, and the
+/// detected format the values are read in.
+#[derive(Debug, Clone, Default)]
+pub struct CsvMapping {
+    pub columns: [Option<usize>; 7],
+    pub format: ContactFileFormat,
+}
+
+impl CsvMapping {
+    /// Maps every field to the first header matching one of its names.
+    pub fn guess(headers: &[String], format: ContactFileFormat) -> CsvMapping {
+        let folded = headers
+            .iter()
+            .map(|header| {
+                deunicode(header)
+                    .to_lowercase()
+                    .replace(['_', '-', '.'], " ")
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join(" ")
+            })
+            .collect::<Vec<String>>();
+        let mut mapping = CsvMapping {
+            format,
+            ..Default::default()
+        };
+        for (index, (_, _, names)) in CSV_FIELDS.iter().enumerate() {
+            mapping.columns[index] = names
+                .iter()
+                .find_map(|name| folded.iter().position(|header| header == name));
+        }
+        mapping
+    }
+    pub fn column(&self, field: &str) -> Option<usize> {
+        CSV_FIELDS
+            .iter()
+            .position(|(key, _, _)| *key == field)
+            .and_then(|index| self.columns[index])
+    }
+    /// Builds the contact for one record, with the values that could not be read.
+    pub fn contact(&self, record: &[String]) -> (Contact, Vec<String>) {
+        let value = |field: &str| {
+            self.column(field)
+                .and_then(|column| record.get(column))
+                .cloned()
+                .unwrap_or_default()
+        };
+        let mut errors = Vec::new();
+        let mut birth_date = value("birth_date");
+        if birth_date == OUTLOOK_NO_DATE {
+            birth_date.clear();
+        }
+        if !birth_date.is_empty() {
+            match parse_csv_date(&birth_date, self.format) {
+                Some(date) => birth_date = date.format("%Y-%m-%d").to_string(),
+                None => errors.push(format!(
+                    "Birth date \"{}\" not understood, use YYYY-MM-DD",
+                    birth_date
+                )),
+            }
+        }
+        let contact = Contact {
+            first_name: value("first_name"),
+            last_name: value("last_name"),
+            phone: value("phone"),
+            email: value("email"),
+            birth_date,
+            address: value("address"),
+            organization: value("organization"),
+            ..Default::default()
+        };
+        (contact, errors)
+    }
+    /// Fields no column is mapped to keep what `contact.id` has stored, so an
+    /// update does not blank them.
+    async fn keep_unmapped(
+        &self,
+        conn: &mut SqliteConnection,
+        contact: &mut Contact,
+    ) -> anyhow::Result<()> {
+        let stored = sqlx::query_as!(
+            Contact,
+            r#"
+            SELECT *
+            FROM contacts_table
+            WHERE id = ?1
+            "#,
+            contact.id
+        )
+        .fetch_one(conn)
+        .await?;
+        if self.column("address").is_none() {
+            contact.address = stored.address;
+        }
+        if self.column("organization").is_none() {
+            contact.organization = stored.organization;
+        }
+        Ok(())
+    }
+    /// Validates one record and applies it on `conn` following `strategy`;
+    /// created contacts belong to `user_id`. The preview runs this inside a
+    /// transaction it rolls back.
+    pub async fn apply_row(
+        &self,
+        line: usize,
+        record: &[String],
+        strategy: DuplicateStrategy,
+        user_id: i64,
+        conn: &mut SqliteConnection,
+    ) -> anyhow::Result<CsvReportRow> {
+        let (mut contact, mut messages) = self.contact(record);
+        contact.user_id = Some(user_id);
+        let duplicate_id = Contact::find_by_email(conn, &contact.email, user_id).await?;
+        // Checked on `conn`, so rows written earlier from this file count too.
+        if let Some(mut errors) = contact.check_contact_errors(&mut *conn).await? {
+            // Duplicates among the user's own contacts are settled by the
+            // strategy below.
+            if duplicate_id.is_some() || strategy == DuplicateStrategy::Create {
+                errors.email_unique_error = String::new();
+            }
+            messages.extend(errors.messages());
+        }
+        let outcome = match messages.is_empty() {
+            false => CsvOutcome::Skipped,
+            true => match (duplicate_id, strategy) {
+                (Some(id), DuplicateStrategy::Skip) => {
+                    contact.id = id;
+                    messages.push(format!("Duplicate of contact {}", id));
+                    CsvOutcome::Skipped
+                }
+                (Some(id), DuplicateStrategy::Update) => {
+                    contact.id = id;
+                    self.keep_unmapped(conn, &mut contact).await?;
+                    contact.update_contact(conn).await?;
+                    CsvOutcome::Updated
+                }
+                (_, _) => {
+                    contact.id = contact.insert_contact(conn).await? as i64;
+                    CsvOutcome::Created
+                }
+            },
+        };
+        Ok(CsvReportRow {
+            line,
+            contact,
+            outcome,
+            messages,
+        })
+    }
+}
+
+/// What to do with a row whose email already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateStrategy {
+    #[default]
+    Skip,
+    Update,
+    Create,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsvOutcome {
+    Created,
+    Updated,
+    Skipped,
+}
+
+impl CsvOutcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsvOutcome::Created => "created",
+            CsvOutcome::Updated => "updated",
+            CsvOutcome::Skipped => "skipped",
+        }
+    }
+}
+
+/// One CSV row after the import, as listed in the preview and the report.
+#[derive(Debug, Clone)]
+pub struct CsvReportRow {
+    /// Line in the file, counting the header as line 1.
+    pub line: usize,
+    pub contact: Contact,
+    pub outcome: CsvOutcome,
+    pub messages: Vec<String>,
+}
+
+pub fn count_outcome(rows: &[CsvReportRow], outcome: CsvOutcome) -> usize {
+    rows.iter().filter(|row| row.outcome == outcome).count()
+}
+
+/// How long a finished CSV import keeps its report, in seconds.
+pub const CSV_JOB_SECONDS: u64 = 3600;
+
+/// A background CSV import and its report so far.
+#[derive(Debug, Clone, Default)]
+pub struct CsvImportJob {
+    pub status: String,
+    pub total: usize,
+    pub report: Vec<CsvReportRow>,
+    pub error: Option<String>,
+    /// Who started it; only they can follow it or download its report.
+    pub user_id: i64,
+    pub finished: Option<std::time::Instant>,
+}
+
+pub type CsvImportStateType = Arc<RwLock<HashMap<u32, CsvImportJob>>>;
+
+impl CsvImportJob {
+    /// Drops a deleted user's jobs, so an account later given the same id
+    /// cannot see them.
+    pub async fn forget_user(csv_import_state: &CsvImportStateType, user_id: i64) {
+        csv_import_state
+            .write()
+            .await
+            .retain(|_, job| job.user_id != user_id);
+    }
+    /// Drops the jobs that finished more than `CSV_JOB_SECONDS` ago.
+    pub async fn evict_finished(csv_import_state: &CsvImportStateType) {
+        csv_import_state.write().await.retain(|_, job| {
+            job.finished
+                .is_none_or(|finished| finished.elapsed().as_secs() <= CSV_JOB_SECONDS)
+        });
+    }
+    pub fn progress(&self) -> f64 {
+        match self.total {
+            0 => 1.0,
+            total => self.report.len() as f64 / total as f64,
+        }
+    }
+    pub fn count(&self, outcome: CsvOutcome) -> usize {
+        count_outcome(&self.report, outcome)
+    }
+    /// The report as CSV, one line per imported row.
+    pub fn report_csv(&self) -> anyhow::Result<String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record([
+            "line",
+            "outcome",
+            "contact_id",
+            "first_name",
+            "last_name",
+            "email",
+            "messages",
+        ])?;
+        for row in &self.report {
+            let contact_id = match row.contact.id {
+                0 => String::new(),
+                id => id.to_string(),
+            };
+            writer.write_record([
+                row.line.to_string().as_str(),
+                row.outcome.name(),
+                contact_id.as_str(),
+                row.contact.first_name.as_str(),
+                row.contact.last_name.as_str(),
+                row.contact.email.as_str(),
+                row.messages.join("; ").as_str(),
+            ])?;
+        }
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+}
+
+/// Imports every record in one transaction, publishing each row's outcome
+/// as it goes so the page can show progress.
+pub async fn run_csv_import(
+    csv_import_state: CsvImportStateType,
+    job_id: u32,
+    pool: Pool<Sqlite>,
+    records: Vec<Vec<String>>,
+    mapping: CsvMapping,
+    strategy: DuplicateStrategy,
+    user_id: i64,
+) {
+    let result: anyhow::Result<()> = async {
+        let mut tx = pool.begin().await?;
+        for (index, record) in records.iter().enumerate() {
+            let row = mapping
+                .apply_row(index + 2, record, strategy, user_id, &mut tx)
+                .await?;
+            if let Some(job) = csv_import_state.write().await.get_mut(&job_id) {
+                job.report.push(row);
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+    .await;
+    if let Some(job) = csv_import_state.write().await.get_mut(&job_id) {
+        job.finished = Some(std::time::Instant::now());
+        match result {
+            Ok(()) => job.status = "Complete".to_owned(),
+            Err(error) => {
+                // The transaction rolled back, so nothing in the report happened.
+                job.status = "Failed".to_owned();
+                job.report.clear();
+                job.error = Some(error.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod csv_import_tests {
+    use super::*;
+
+    #[test]
+    fn detect_delimiter_ignores_quoted_delimiters() {
+        let text = "name;email;note\n\"Doe, Jane\";jane@x.io;\"a, b, c\"\nBob;bob@x.io;\n";
+        assert_eq!(detect_delimiter(text), b';');
+    }
+
+    #[test]
+    fn detect_delimiter_finds_tabs_and_falls_back_to_comma() {
+        assert_eq!(detect_delimiter("a\tb\tc\n1\t2\t3\n"), b'\t');
+        assert_eq!(detect_delimiter("a|b\n1|2\n"), b'|');
+        assert_eq!(detect_delimiter("name\nJane\n"), b',');
+        assert_eq!(detect_delimiter(""), b',');
+    }
+
+    #[test]
+    fn decode_csv_prefers_bom_then_utf8() {
+        let (text, encoding) = decode_csv(b"\xef\xbb\xbfname\nM\xc3\xbcller\n");
+        assert_eq!((text.as_str(), encoding), ("name\nMüller\n", "UTF-8"));
+        let (text, encoding) = decode_csv(b"\xff\xfen\x00a\x00");
+        assert_eq!((text.as_str(), encoding), ("na", "UTF-16LE"));
+        let (text, encoding) = decode_csv("name\nZoë\n".as_bytes());
+        assert_eq!((text.as_str(), encoding), ("name\nZoë\n", "UTF-8"));
+    }
+
+    #[test]
+    fn decode_csv_guesses_legacy_encodings() {
+        let bytes = b"first name;last name;city\nJ\xfcrgen;M\xfcller;M\xfcnchen\n\
+            Fran\xe7ois;L\xe9v\xeaque;Orl\xe9ans\nS\xf8ren;\xc5berg;K\xf8benhavn\n";
+        let (text, encoding) = decode_csv(bytes);
+        assert_eq!(encoding, "windows-1252");
+        assert!(text.contains("Jürgen;Müller;München"));
+        assert!(text.contains("François;Lévêque"));
+    }
+}
+
+// endregion: CSV IMPORT
+
+// region: ADDRESS BOOKS
+
+/// Header of a Google Contacts CSV export.
+pub const GOOGLE_CSV_HEADERS: [&str; 21] = [
+    "First Name",
+    "Middle Name",
+    "Last Name",
+    "Phonetic First Name",
+    "Phonetic Middle Name",
+    "Phonetic Last Name",
+    "Name Prefix",
+    "Name Suffix",
+    "Nickname",
+    "File As",
+    "Organization Name",
+    "Organization Title",
+    "Organization Department",
+    "Birthday",
+    "Notes",
+    "Photo",
+    "Labels",
+    "E-mail 1 - Label",
+    "E-mail 1 - Value",
+    "Phone 1 - Label",
+    "Phone 1 - Value",
+];
+
+/// Header of an Outlook "Comma Separated Values" contacts export.
+pub const OUTLOOK_CSV_HEADERS: [&str; 92] = [
+    "Title",
+    "First Name",
+    "Middle Name",
+    "Last Name",
+    "Suffix",
+    "Company",
+    "Department",
+    "Job Title",
+    "Business Street",
+    "Business Street 2",
+    "Business Street 3",
+    "Business City",
+    "Business State",
+    "Business Postal Code",
+    "Business Country/Region",
+    "Home Street",
+    "Home Street 2",
+    "Home Street 3",
+    "Home City",
+    "Home State",
+    "Home Postal Code",
+    "Home Country/Region",
+    "Other Street",
+    "Other Street 2",
+    "Other Street 3",
+    "Other City",
+    "Other State",
+    "Other Postal Code",
+    "Other Country/Region",
+    "Assistant's Phone",
+    "Business Fax",
+    "Business Phone",
+    "Business Phone 2",
+    "Callback",
+    "Car Phone",
+    "Company Main Phone",
+    "Home Fax",
+    "Home Phone",
+    "Home Phone 2",
+    "ISDN",
+    "Mobile Phone",
+    "Other Fax",
+    "Other Phone",
+    "Pager",
+    "Primary Phone",
+    "Radio Phone",
+    "TTY/TDD Phone",
+    "Telex",
+    "Account",
+    "Anniversary",
+    "Assistant's Name",
+    "Billing Information",
+    "Birthday",
+    "Business Address PO Box",
+    "Categories",
+    "Children",
+    "Directory Server",
+    "E-mail Address",
+    "E-mail Type",
+    "E-mail Display Name",
+    "E-mail 2 Address",
+    "E-mail 2 Type",
+    "E-mail 2 Display Name",
+    "E-mail 3 Address",
+    "E-mail 3 Type",
+    "E-mail 3 Display Name",
+    "Gender",
+    "Government ID Number",
+    "Hobby",
+    "Home Address PO Box",
+    "Initials",
+    "Internet Free Busy",
+    "Keywords",
+    "Language",
+    "Location",
+    "Manager's Name",
+    "Mileage",
+    "Notes",
+    "Office Location",
+    "Organizational ID Number",
+    "Other Address PO Box",
+    "Priority",
+    "Private",
+    "Profession",
+    "Referred By",
+    "Sensitivity",
+    "Spouse",
+    "User 1",
+    "User 2",
+    "User 3",
+    "User 4",
+    "Web Page",
+];
+
+/// Outlook's empty birthday.
+pub const OUTLOOK_NO_DATE: &str = "0/0/00";
+
+/// The kinds of address book files the importers recognise.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ContactFileFormat {
+    VCard,
+    #[default]
+    Csv,
+    GoogleCsv,
+    OutlookCsv,
+    Ldif,
+}
+
+impl ContactFileFormat {
+    /// Tells the format from the content alone: vCard and LDIF by their first
+    /// line, the Google and Outlook layouts by columns only they have.
+    pub fn detect(text: &str) -> ContactFileFormat {
+        let first_line = text
+            .lines()
+            .map(|line| line.trim_start_matches('\u{feff}').trim())
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .unwrap_or("")
+            .to_lowercase();
+        if first_line.starts_with("begin:vcard") {
+            return ContactFileFormat::VCard;
+        }
+        if first_line.starts_with("version:") || first_line.starts_with("dn:") {
+            return ContactFileFormat::Ldif;
+        }
+        let has = |column: &str| first_line.contains(&column.to_lowercase());
+        if has("E-mail 1 - Value") || (has("Given Name") && has("Group Membership")) {
+            ContactFileFormat::GoogleCsv
+        } else if has("E-mail Address") && has("Mobile Phone") {
+            ContactFileFormat::OutlookCsv
+        } else {
+            ContactFileFormat::Csv
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContactFileFormat::VCard => "vCard",
+            ContactFileFormat::Csv => "CSV",
+            ContactFileFormat::GoogleCsv => "Google Contacts CSV",
+            ContactFileFormat::OutlookCsv => "Outlook CSV",
+            ContactFileFormat::Ldif => "LDIF",
+        }
+    }
+}
+
+impl Contact {
+    fn birth(&self) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(&self.birth_date, "%Y-%m-%d").ok()
+    }
+    /// The birth date as shown and exported, `--MM-DD` when the year is unknown.
+    pub fn birth_date_display(&self) -> String {
+        match self.birth() {
+            Some(birth) if birth.year() == NO_YEAR => birth.format("--%m-%d").to_string(),
+            _ => self.birth_date.clone(),
+        }
+    }
+    /// This contact in the columns of `GOOGLE_CSV_HEADERS`.
+    pub fn to_google_record(&self) -> Vec<String> {
+        let mut record = vec![String::new(); GOOGLE_CSV_HEADERS.len()];
+        let mut set = |column: &str, value: String| {
+            if let Some(index) = GOOGLE_CSV_HEADERS.iter().position(|c| *c == column) {
+                record[index] = value;
+            }
+        };
+        set("First Name", self.first_name.clone());
+        set("Last Name", self.last_name.clone());
+        set(
+            "File As",
+            format!("{} {}", self.first_name.trim(), self.last_name.trim())
+                .trim()
+                .to_owned(),
+        );
+        set("Labels", "* myContacts".to_owned());
+        if let Some(birth) = self.birth() {
+            set(
+                "Birthday",
+                match birth.year() {
+                    NO_YEAR => birth.format("--%m-%d").to_string(),
+                    _ => birth.format("%Y-%m-%d").to_string(),
+                },
+            );
+        }
+        if !self.email.trim().is_empty() {
+            set("E-mail 1 - Label", "* Other".to_owned());
+            set("E-mail 1 - Value", self.email.trim().to_owned());
+        }
+        if !self.phone.trim().is_empty() {
+            set("Phone 1 - Label", "Mobile".to_owned());
+            set("Phone 1 - Value", self.phone.trim().to_owned());
+        }
+        record
+    }
+    /// This contact in the columns of `OUTLOOK_CSV_HEADERS`. Outlook has no
+    /// birthdays without a year, so those are left out.
+    pub fn to_outlook_record(&self) -> Vec<String> {
+        let mut record = vec![String::new(); OUTLOOK_CSV_HEADERS.len()];
+        let mut set = |column: &str, value: String| {
+            if let Some(index) = OUTLOOK_CSV_HEADERS.iter().position(|c| *c == column) {
+                record[index] = value;
+            }
+        };
+        set("First Name", self.first_name.clone());
+        set("Last Name", self.last_name.clone());
+        set("Mobile Phone", self.phone.trim().to_owned());
+        set("Anniversary", OUTLOOK_NO_DATE.to_owned());
+        set(
+            "Birthday",
+            match self.birth() {
+                Some(birth) if birth.year() != NO_YEAR => birth.format("%-m/%-d/%Y").to_string(),
+                _ => OUTLOOK_NO_DATE.to_owned(),
+            },
+        );
+        if !self.email.trim().is_empty() {
+            set("E-mail Address", self.email.trim().to_owned());
+            set("E-mail Type", "SMTP".to_owned());
+            set(
+                "E-mail Display Name",
+                format!(
+                    "{} {} ({})",
+                    self.first_name.trim(),
+                    self.last_name.trim(),
+                    self.email.trim()
+                ),
+            );
+        }
+        set("Sensitivity", "Normal".to_owned());
+        set("Private", "False".to_owned());
+        set("Priority", "Normal".to_owned());
+        set("Gender", "Unspecified".to_owned());
+        record
+    }
+    /// This contact as an LDIF entry for the inetOrgPerson schema, with the
+    /// birthday attributes Thunderbird uses.
+    pub fn to_ldif(&self) -> String {
+        let first_name = self.first_name.trim();
+        let last_name = self.last_name.trim();
+        let name = format!("{} {}", first_name, last_name).trim().to_owned();
+        let mut dn = format!("cn={}", escape_dn(&name));
+        if !self.email.trim().is_empty() {
+            dn.push_str(&format!(",mail={}", escape_dn(self.email.trim())));
+        }
+        let mut lines = vec![ldif_line("dn", &dn)];
+        for class in [
+            "top",
+            "person",
+            "organizationalPerson",
+            "inetOrgPerson",
+            "mozillaAbPersonAlpha",
+        ] {
+            lines.push(ldif_line("objectclass", class));
+        }
+        let mut attributes = vec![("cn", name.clone()), ("sn", last_name.to_owned())];
+        if !first_name.is_empty() {
+            attributes.push(("givenName", first_name.to_owned()));
+        }
+        if !self.email.trim().is_empty() {
+            attributes.push(("mail", self.email.trim().to_owned()));
+        }
+        if !self.phone.trim().is_empty() {
+            attributes.push(("mobile", self.phone.trim().to_owned()));
+        }
+        if let Some(birth) = self.birth() {
+            if birth.year() != NO_YEAR {
+                attributes.push(("birthyear", birth.year().to_string()));
+            }
+            attributes.push(("birthmonth", birth.format("%m").to_string()));
+            attributes.push(("birthday", birth.format("%d").to_string()));
+        }
+        for (attribute, value) in attributes {
+            lines.push(ldif_line(attribute, &value));
+        }
+        lines.push("\n".to_owned());
+        lines.concat()
+    }
+}
+
+/// Escapes a DN attribute value as RFC 4514 asks.
+fn escape_dn(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (index, c) in value.chars().enumerate() {
+        let edge = (index == 0 && (c == ' ' || c == '#')) || (c == ' ' && value.ends_with(' '));
+        if edge || ",+\"\\<>;=".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// One `attribute: value` line, base64 encoded (`::`) when the value is not a
+/// SAFE-STRING of RFC 2849, and folded at 76 columns.
+fn ldif_line(attribute: &str, value: &str) -> String {
+    let safe = value
+        .bytes()
+        .all(|byte| byte.is_ascii() && !b"\0\r\n".contains(&byte))
+        && !value.starts_with([' ', ':', '<'])
+        && !value.ends_with(' ');
+    let line = match safe {
+        true => format!("{}: {}", attribute, value),
+        false => format!("{}:: {}", attribute, BASE64.encode(value)),
+    };
+    // Every line is ASCII by now, so bytes and characters line up.
+    let mut folded = String::with_capacity(line.len() + 4);
+    for (index, chunk) in line.as_bytes().chunks(75).enumerate() {
+        if index > 0 {
+            folded.push_str("\n ");
+        }
+        folded.push_str(std::str::from_utf8(chunk).unwrap_or(""));
+    }
+    folded.push('\n');
+    folded
+}
+
+/// Google Contacts CSV, UTF-8, in Google's own column layout.
+pub fn export_google_csv(contacts_set: &[Contact], header: bool) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(Vec::new());
+    if header {
+        writer.write_record(GOOGLE_CSV_HEADERS)?;
+    }
+    for contact in contacts_set {
+        writer.write_record(contact.to_google_record())?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// Outlook CSV: every field quoted like Outlook writes them, and a BOM so
+/// Outlook reads the file as UTF-8.
+pub fn export_outlook_csv(contacts_set: &[Contact], header: bool) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    if header {
+        bytes.extend_from_slice("\u{feff}".as_bytes());
+    }
+    let mut writer = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::CRLF)
+        .quote_style(csv::QuoteStyle::Always)
+        .from_writer(bytes);
+    if header {
+        writer.write_record(OUTLOOK_CSV_HEADERS)?;
+    }
+    for contact in contacts_set {
+        writer.write_record(contact.to_outlook_record())?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// LDIF entries; the first chunk starts with the version line.
+pub fn export_ldif(contacts_set: &[Contact], first: bool) -> Vec<u8> {
+    let mut ldif = match first {
+        true => "version: 1\n\n".to_owned(),
+        false => String::new(),
+    };
+    for contact in contacts_set {
+        ldif.push_str(&contact.to_ldif());
+    }
+    ldif.into_bytes()
+}
+
+/// Reads LDIF content records into the same header and rows a CSV upload
+/// gives, so they go through the CSV mapping, dry run and import.
+pub fn read_ldif(text: &str) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        if let (Some(continued), Some(last)) = (line.strip_prefix(' '), lines.last_mut()) {
+            if !last.is_empty() {
+                last.push_str(continued);
+                continue;
+            }
+        }
+        lines.push(line.to_owned());
+    }
+    let mut records = Vec::new();
+    let mut entry: HashMap<String, String> = HashMap::new();
+    for line in lines.iter().chain(std::iter::once(&String::new())) {
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.trim().is_empty() {
+            if entry.contains_key("dn") {
+                records.push(ldif_record(&entry));
+            }
+            entry.clear();
+            continue;
+        }
+        let Some((attribute, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Options such as `;lang-de` are dropped; `:<` URLs are not fetched.
+        let attribute = attribute.split(';').next().unwrap_or("").to_lowercase();
+        let value = if let Some(encoded) = value.strip_prefix(':') {
+            BASE64
+                .decode(encoded.trim())
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+                .unwrap_or_default()
+        } else if value.starts_with('<') {
+            continue;
+        } else {
+            value.trim().to_owned()
+        };
+        entry.entry(attribute).or_insert(value);
+    }
+    let headers = CSV_FIELDS
+        .iter()
+        .map(|(_, label, _)| label.to_string())
+        .collect();
+    (headers, records)
+}
+
+/// One entry as a row in `CSV_FIELDS` order.
+fn ldif_record(entry: &HashMap<String, String>) -> Vec<String> {
+    let value = |attributes: &[&str]| {
+        attributes
+            .iter()
+            .find_map(|attribute| entry.get(*attribute).filter(|value| !value.is_empty()))
+            .cloned()
+            .unwrap_or_default()
+    };
+    let mut first_name = value(&["givenname"]);
+    let mut last_name = value(&["sn", "surname"]);
+    if first_name.is_empty() && last_name.is_empty() {
+        let name = value(&["cn", "displayname"]);
+        match name.rsplit_once(' ') {
+            Some((first, last)) => (first_name, last_name) = (first.to_owned(), last.to_owned()),
+            None => last_name = name,
+        }
+    }
+    let (year, month, day) = (
+        value(&["birthyear"]),
+        value(&["birthmonth"]),
+        value(&["birthday"]),
+    );
+    let birth_date = match (year.is_empty(), month.is_empty() || day.is_empty()) {
+        (_, true) => String::new(),
+        (true, false) => format!("--{:0>2}{:0>2}", month, day),
+        (false, false) => format!("{}-{:0>2}-{:0>2}", year, month, day),
+    };
+    vec![
+        first_name,
+        last_name,
+        value(&["mobile", "telephonenumber", "homephone"]),
+        value(&["mail"]),
+        birth_date,
+        // Lines of a postal address are separated by `$`.
+        value(&["postaladdress", "homepostaladdress", "street"])
+            .split('$')
+            .map(str::trim)
+            .collect::<Vec<&str>>()
+            .join(", "),
+        value(&["o", "company"]),
+    ]
+}
+
+/// Writes rows back out as comma separated text, for the CSV import forms.
+pub fn write_csv(headers: &[String], records: &[Vec<String>]) -> anyhow::Result<String> {
+    let mut writer = csv::WriterBuilder::new().from_writer(Vec::new());
+    writer.write_record(headers)?;
+    for record in records {
+        writer.write_record(record)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod address_book_tests {
+    use super::*;
+
+    #[test]
+    fn ldif_round_trips_through_base64_and_folding() {
+        let contact = Contact {
+            first_name: "Zoë".to_owned(),
+            last_name: "Lévêque-Montmorency-Laval de la Rochefoucauld".to_owned(),
+            phone: "+33 1 23 45 67 89".to_owned(),
+            email: "zoe@example.fr".to_owned(),
+            birth_date: "1990-04-15".to_owned(),
+            ..Contact::default()
+        };
+        let ldif = String::from_utf8(export_ldif(&[contact], true)).unwrap();
+        assert!(ldif.starts_with("version: 1\n\n"));
+        assert!(ldif.contains("givenName:: "));
+        assert!(ldif.lines().all(|line| line.len() <= 76));
+        let (_, records) = read_ldif(&ldif);
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0][..5],
+            [
+                "Zoë",
+                "Lévêque-Montmorency-Laval de la Rochefoucauld",
+                "+33 1 23 45 67 89",
+                "zoe@example.fr",
+                "1990-04-15",
+            ]
+        );
+    }
+
+    #[test]
+    fn read_ldif_falls_back_to_the_common_name() {
+        let (_, records) = read_ldif(
+            "# comment\ndn: cn=Ada Lovelace\ncn: Ada Lovelace\nbirthmonth: 12\nbirthday: 10\n",
+        );
+        assert_eq!(records[0][..2], ["Ada", "Lovelace"]);
+        assert_eq!(records[0][4], "--1210");
+    }
+}
+
+// endregion: ADDRESS BOOKS
+
+// region: MAILBOX IMPORT
+
+use mail_parser::mailbox::mbox::MessageIterator;
+use mail_parser::MessageParser;
+
+/// Local parts of addresses that send mail but never read it; they are listed
+/// unticked.
+const AUTOMATED_SENDERS: [&str; 6] = [
+    "noreply",
+    "no-reply",
+    "donotreply",
+    "do-not-reply",
+    "mailer-daemon",
+    "postmaster",
+];
+
+/// Someone found in the From, To or Cc headers of the uploaded mail.
+#[derive(Debug, Clone, Default)]
+pub struct Correspondent {
+    pub name: String,
+    pub email: String,
+    /// Messages the address appears in.
+    pub messages: usize,
+    /// One of the user's contacts has this email already, as
+    /// `Contact::validate_email` sees it.
+    pub existing: bool,
+}
+
+impl Correspondent {
+    pub fn is_automated(&self) -> bool {
+        let local = self.email.split('@').next().unwrap_or("").to_lowercase();
+        AUTOMATED_SENDERS
+            .iter()
+            .any(|sender| local.starts_with(sender))
+    }
+    /// "Doe, Jane" and "Jane Doe" both give Jane / Doe; without a display
+    /// name the address's local part stands in for the first name.
+    pub fn split_name(&self) -> (String, String) {
+        let name = self.name.trim().trim_matches(['"', '\'']).trim();
+        if let Some((last, first)) = name.split_once(',') {
+            return (first.trim().to_owned(), last.trim().to_owned());
+        }
+        match name.rsplit_once(' ') {
+            Some((first, last)) => (first.trim().to_owned(), last.trim().to_owned()),
+            None if !name.is_empty() => (name.to_owned(), String::new()),
+            None => (
+                self.email.split('@').next().unwrap_or("").to_owned(),
+                String::new(),
+            ),
+        }
+    }
+}
+
+/// Raw messages of one upload: an mbox file when it starts with a `From `
+/// separator line, otherwise a single `.eml` message.
+pub fn mailbox_messages(bytes: &[u8]) -> Vec<Vec<u8>> {
+    match bytes.starts_with(b"From ") {
+        true => MessageIterator::new(bytes)
+            .filter_map(|message| message.ok())
+            .map(|message| message.unwrap_contents())
+            .collect(),
+        false => vec![bytes.to_vec()],
+    }
+}
+
+/// Every address in the From, To and Cc headers, merged case-insensitively and
+/// sorted by how many messages it appears in. Returns them with the number of
+/// messages read.
+pub fn read_correspondents(messages_raw: &[Vec<u8>]) -> (Vec<Correspondent>, usize) {
+    let parser = MessageParser::default();
+    let mut found: HashMap<String, Correspondent> = HashMap::new();
+    let mut read = 0;
+    for raw in messages_raw {
+        let Some(message) = parser.parse_headers(raw) else {
+            continue;
+        };
+        read += 1;
+        let mut seen = HashSet::new();
+        let headers = [message.from(), message.to(), message.cc()];
+        for addr in headers
+            .into_iter()
+            .flatten()
+            .flat_map(|address| address.iter())
+        {
+            let email = addr.address().unwrap_or("").trim();
+            if !email.contains('@') || email.contains(char::is_whitespace) {
+                continue;
+            }
+            let key = email.to_lowercase();
+            let correspondent = found.entry(key.clone()).or_insert_with(|| Correspondent {
+                email: email.to_owned(),
+                ..Default::default()
+            });
+            let name = addr.name().unwrap_or("").trim();
+            if correspondent.name.is_empty() && !name.is_empty() && !name.contains('@') {
+                correspondent.name = name.to_owned();
+            }
+            if seen.insert(key) {
+                correspondent.messages += 1;
+            }
+        }
+    }
+    let mut correspondents = found.into_values().collect::<Vec<Correspondent>>();
+    correspondents.sort_by(|a, b| {
+        b.messages
+            .cmp(&a.messages)
+            .then_with(|| a.email.to_lowercase().cmp(&b.email.to_lowercase()))
+    });
+    (correspondents, read)
+}
+
+impl Correspondent {
+    /// Marks the correspondents `user_id` has as contacts; other accounts'
+    /// contacts are not looked at.
+    pub async fn mark_existing(
+        correspondents: &mut [Correspondent],
+        user_id: i64,
+        pool: &Pool<Sqlite>,
+    ) -> anyhow::Result<()> {
+        for correspondent in correspondents.iter_mut() {
+            correspondent.existing =
+                !Contact::validate_email(pool, &correspondent.email, 0, user_id)
+                    .await?
+                    .is_empty();
+        }
+        Ok(())
+    }
+}
+
+// endregion: MAILBOX IMPORT
+
+// region: SIGNATURE PARSER
+
+/// Closing lines that come before the signature and say nothing about the sender.
+const SIGN_OFFS: [&str; 16] = [
+    "best",
+    "best regards",
+    "best wishes",
+    "regards",
+    "kind regards",
+    "warm regards",
+    "cheers",
+    "thanks",
+    "many thanks",
+    "thank you",
+    "sincerely",
+    "yours sincerely",
+    "yours truly",
+    "all the best",
+    "cordially",
+    "respectfully",
+];
+
+/// Labels put before a phone number; a labelled number is taken as is.
+const PHONE_LABELS: [&str; 19] = [
+    "t",
+    "tel",
+    "telephone",
+    "phone",
+    "ph",
+    "p",
+    "m",
+    "mob",
+    "mobile",
+    "cell",
+    "c",
+    "o",
+    "office",
+    "d",
+    "direct",
+    "w",
+    "work",
+    "h",
+    "home",
+];
+
+const FAX_LABELS: [&str; 3] = ["f", "fax", "telefax"];
+
+const WEB_LABELS: [&str; 4] = ["w", "web", "website", "site"];
+
+const ORGANIZATION_LABELS: [&str; 5] = ["company", "organization", "organisation", "org", "firm"];
+
+/// Last words of a company name, compared without dots or case.
+const COMPANY_SUFFIXES: [&str; 25] = [
+    "inc",
+    "llc",
+    "llp",
+    "ltd",
+    "limited",
+    "plc",
+    "corp",
+    "corporation",
+    "co",
+    "company",
+    "gmbh",
+    "ag",
+    "kg",
+    "sa",
+    "sas",
+    "sarl",
+    "srl",
+    "spa",
+    "bv",
+    "nv",
+    "ab",
+    "oy",
+    "pty",
+    "lda",
+    "ltda",
+];
+
+/// Words of a job title line, which sits between the name and the company.
+const TITLE_WORDS: [&str; 28] = [
+    "ceo",
+    "cto",
+    "cfo",
+    "coo",
+    "vp",
+    "president",
+    "founder",
+    "co-founder",
+    "owner",
+    "chief",
+    "head",
+    "director",
+    "manager",
+    "lead",
+    "senior",
+    "engineer",
+    "developer",
+    "designer",
+    "consultant",
+    "officer",
+    "partner",
+    "associate",
+    "analyst",
+    "specialist",
+    "coordinator",
+    "assistant",
+    "representative",
+    "advisor",
+];
+
+/// Street words, compared without dots or case, that mark an address line.
+const STREET_WORDS: [&str; 36] = [
+    "street",
+    "st",
+    "avenue",
+    "ave",
+    "av",
+    "road",
+    "rd",
+    "boulevard",
+    "blvd",
+    "lane",
+    "ln",
+    "drive",
+    "dr",
+    "way",
+    "court",
+    "ct",
+    "place",
+    "pl",
+    "square",
+    "sq",
+    "suite",
+    "ste",
+    "floor",
+    "apt",
+    "unit",
+    "highway",
+    "parkway",
+    "box",
+    "rua",
+    "avenida",
+    "calle",
+    "strasse",
+    "straße",
+    "str",
+    "via",
+    "rue",
+];
+
+/// Lowercase particles allowed inside a name, as in Elvis Presley da Silva.
+const NAME_PARTICLES: [&str; 12] = [
+    "da", "de", "di", "do", "dos", "das", "del", "della", "van", "von", "der", "le",
+];
+
+const HONORIFICS: [&str; 6] = ["dr", "mr", "mrs", "ms", "miss", "prof"];
+
+/// Mail providers whose domain says nothing about the sender's organization.
+const FREE_MAIL_DOMAINS: [&str; 16] = [
+    "gmail.com",
+    "googlemail.com",
+    "yahoo.com",
+    "hotmail.com",
+    "outlook.com",
+    "live.com",
+    "msn.com",
+    "icloud.com",
+    "me.com",
+    "aol.com",
+    "proton.me",
+    "protonmail.com",
+    "gmx.com",
+    "gmx.de",
+    "mail.com",
+    "yandex.com",
+];
+
+/// What `parse_signature` made of a pasted signature. Fields it found nothing
+/// for are left empty.
+#[derive(Debug, Default)]
+pub struct ParsedSignature {
+    pub contact: Contact,
+    /// Form field names of the values that were guessed rather than recognised.
+    pub uncertain: Vec<String>,
+}
+
+impl ParsedSignature {
+    fn set(&mut self, field: &str, value: String, sure: bool) {
+        match field {
+            "first_name" => self.contact.first_name = value,
+            "last_name" => self.contact.last_name = value,
+            "phone" => self.contact.phone = value,
+            "email" => self.contact.email = value,
+            "organization" => self.contact.organization = value,
+            "address" => self.contact.address = value,
+            _ => return,
+        }
+        self.uncertain.retain(|uncertain| uncertain != field);
+        if !sure {
+            self.uncertain.push(field.to_owned());
+        }
+    }
+    fn is_uncertain(&self, field: &str) -> bool {
+        self.uncertain.iter().any(|uncertain| uncertain == field)
+    }
+}
+
+/// A word lowercased without the dots and commas around or inside it, so
+/// "Inc." and "S.A." compare as "inc" and "sa".
+fn bare_word(word: &str) -> String {
+    word.chars()
+        .filter(|c| !matches!(c, '.' | ','))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn is_sign_off(line: &str) -> bool {
+    let lower = line.trim_end_matches([',', '.', '!']).to_lowercase();
+    SIGN_OFFS.contains(&lower.as_str()) || lower.starts_with("sent from")
+}
+
+/// "Mobile: +1 555 0100" and "M +1 555 0100" both give ("mobile"/"m", the number);
+/// lines without a label give an empty one.
+fn split_label(line: &str) -> (String, &str) {
+    if let Some((label, value)) = line.split_once(':') {
+        let label = label.trim();
+        if !label.is_empty()
+            && label.chars().count() <= 12
+            && label
+                .chars()
+                .all(|c| c.is_alphabetic() || matches!(c, ' ' | '.' | '-'))
+        {
+            return (bare_word(label), value.trim());
+        }
+    }
+    if let Some((label, value)) = line.split_once(' ') {
+        let value = value.trim_start();
+        if PHONE_LABELS.contains(&bare_word(label).as_str())
+            && value.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '(')
+        {
+            return (bare_word(label), value);
+        }
+    }
+    (String::new(), line)
+}
+
+fn is_url(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("www.")
+}
+
+/// The first word that reads as an email address, without `mailto:` or the
+/// brackets around it.
+fn find_email(value: &str) -> Option<&str> {
+    value
+        .split([' ', ',', ';'])
+        .map(|word| {
+            let word = word.trim_matches(['<', '>', '(', ')', '[', ']', '"', '\'', '.', ':']);
+            word.strip_prefix("mailto:").unwrap_or(word)
+        })
+        .find(|word| match word.split_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty()
+                    && domain.contains('.')
+                    && !domain.contains('@')
+                    && !domain.starts_with('.')
+            }
+            None => false,
+        })
+}
+
+/// The number without any extension, when the value holds nothing but 7 to 15
+/// digits and the usual separators.
+fn phone_number(value: &str) -> Option<String> {
+    let lower = value.to_lowercase();
+    let end = ["ext", " x", "#"]
+        .iter()
+        .filter_map(|marker| lower.find(marker))
+        .min()
+        .unwrap_or(value.len());
+    let number = value.get(..end)?.trim();
+    let digits = number.chars().filter(char::is_ascii_digit).count();
+    let separators =
+        |c: char| c.is_ascii_digit() || matches!(c, ' ' | '+' | '-' | '(' | ')' | '.' | '/');
+    match (7..=15).contains(&digits) && number.chars().all(separators) {
+        true => Some(number.to_owned()),
+        false => None,
+    }
+}
+
+/// Whether the line looks like part of a postal address: it has a number and
+/// either a street word, a postal code or a leading house number. Returns
+/// `Some(true)` when a street word made it certain.
+fn address_line(line: &str) -> Option<bool> {
+    if !line.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let words = line
+        .split_whitespace()
+        .map(bare_word)
+        .collect::<Vec<String>>();
+    if words
+        .iter()
+        .any(|word| STREET_WORDS.contains(&word.as_str()))
+    {
+        return Some(true);
+    }
+    let postal_code = words.iter().any(|word| {
+        let parts = word.split('-').collect::<Vec<&str>>();
+        parts
+            .iter()
+            .all(|part| part.chars().all(|c| c.is_ascii_digit()))
+            && match parts.as_slice() {
+                [code] => (4..=6).contains(&code.len()),
+                [code, suffix] => (4..=5).contains(&code.len()) && (3..=4).contains(&suffix.len()),
+                _ => false,
+            }
+    });
+    let house_number = words
+        .first()
+        .is_some_and(|word| word.starts_with(|c: char| c.is_ascii_digit()) && words.len() > 1)
+        && line.chars().any(char::is_alphabetic);
+    match postal_code || house_number {
+        true => Some(false),
+        false => None,
+    }
+}
+
+fn is_company(line: &str) -> bool {
+    let words = line.split_whitespace().collect::<Vec<&str>>();
+    words.len() > 1
+        && words
+            .last()
+            .is_some_and(|word| COMPANY_SUFFIXES.contains(&bare_word(word).as_str()))
+}
+
+fn is_title(line: &str) -> bool {
+    line.split([' ', ',', '/', '&'])
+        .any(|word| TITLE_WORDS.contains(&bare_word(word).as_str()))
+}
+
+/// First name and the rest as last name, when the line is two to four
+/// capitalised words, ignoring an honorific before and credentials after a comma.
+fn name_line(line: &str) -> Option<(String, String)> {
+    if is_company(line) || is_title(line) {
+        return None;
+    }
+    let line = line.split(',').next()?;
+    let words = line
+        .split_whitespace()
+        .skip_while(|word| HONORIFICS.contains(&bare_word(word).as_str()))
+        .collect::<Vec<&str>>();
+    let capitalised = |word: &&str| {
+        word.starts_with(char::is_uppercase)
+            && word
+                .chars()
+                .all(|c| c.is_alphabetic() || matches!(c, '-' | '\'' | '.'))
+    };
+    let named = (2..=4).contains(&words.len())
+        && words.first().is_some_and(capitalised)
+        && words.last().is_some_and(capitalised)
+        && words
+            .iter()
+            .all(|word| capitalised(word) || NAME_PARTICLES.contains(word));
+    match named {
+        true => Some((words[0].to_owned(), words[1..].join(" "))),
+        false => None,
+    }
+}
+
+fn capitalise(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The organization name a company domain suggests: `mail.acme.co.uk` gives Acme.
+fn domain_organization(email: &str) -> Option<String> {
+    let domain = email.rsplit_once('@')?.1.to_lowercase();
+    if FREE_MAIL_DOMAINS.contains(&domain.as_str()) {
+        return None;
+    }
+    domain
+        .split('.')
+        .rev()
+        .skip(1)
+        .find(|label| !["co", "com", "org", "net", "ac", "gov", "edu"].contains(label))
+        .map(capitalise)
+}
+
+/// Reads the fields of a contact out of a pasted email signature. Email
+/// addresses, labelled phone numbers, company suffixes and street words are
+/// recognised; everything else is a guess from where the line sits, and the
+/// guessed fields are listed in `uncertain`.
+pub fn parse_signature(text: &str) -> ParsedSignature {
+    let mut parsed = ParsedSignature::default();
+    let lines = text
+        .lines()
+        .flat_map(|line| line.split(['|', '•', '·']))
+        .map(|line| line.trim().trim_start_matches(['-', '–', '—', '_']).trim())
+        .filter(|line| line.chars().any(char::is_alphanumeric) && !is_sign_off(line));
+
+    let mut rest: Vec<String> = Vec::new();
+    let mut address: Vec<&str> = Vec::new();
+    let mut address_sure = false;
+    let mut address_ended = false;
+    for line in lines {
+        if is_url(line) {
+            continue;
+        }
+        let (label, value) = split_label(line);
+        if let Some(email) = find_email(value) {
+            if parsed.contact.email.is_empty() {
+                parsed.set("email", email.to_owned(), true);
+            }
+            let left = value
+                .replace(email, "")
+                .replace("mailto:", "")
+                .trim_matches([
+                    '<', '>', '(', ')', '[', ']', '"', '\'', ',', ';', ':', '-', ' ',
+                ])
+                .to_owned();
+            if label.is_empty() && !left.is_empty() {
+                rest.push(left);
+            }
+            continue;
+        }
+        if FAX_LABELS.contains(&label.as_str()) || WEB_LABELS.contains(&label.as_str()) {
+            continue;
+        }
+        if ORGANIZATION_LABELS.contains(&label.as_str()) {
+            parsed.set("organization", value.to_owned(), true);
+            continue;
+        }
+        if let Some(phone) = phone_number(value) {
+            let labelled = PHONE_LABELS.contains(&label.as_str());
+            if parsed.contact.phone.is_empty() || (labelled && parsed.is_uncertain("phone")) {
+                parsed.set("phone", phone, labelled);
+            }
+            continue;
+        }
+        // The address is the first run of address lines; anything else ends it.
+        if !address_ended {
+            if let Some(sure) = address_line(value) {
+                address_sure |= sure;
+                address.push(value);
+                continue;
+            }
+        }
+        address_ended = !address.is_empty();
+        rest.push(line.to_owned());
+    }
+    if !address.is_empty() {
+        parsed.set("address", address.join("\n"), address_sure);
+    }
+
+    let email_local = deunicode(parsed.contact.email.split('@').next().unwrap_or(""))
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "");
+    let name_at = rest.iter().position(|line| name_line(line).is_some());
+    match name_at.and_then(|at| name_line(&rest[at])) {
+        Some((first_name, last_name)) => {
+            let sure = first_name
+                .split(['-', ' '])
+                .chain(last_name.split(['-', ' ']))
+                .map(|word| deunicode(word).to_lowercase())
+                .any(|word| word.len() > 2 && email_local.contains(&word));
+            parsed.set("first_name", first_name, sure);
+            parsed.set("last_name", last_name, sure);
+        }
+        None => {
+            let local = parsed.contact.email.split('@').next().unwrap_or("");
+            let parts = local
+                .split(['.', '_', '-'])
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<&str>>();
+            if parts.len() > 1
+                && parts
+                    .iter()
+                    .all(|part| part.chars().all(char::is_alphabetic))
+            {
+                let (first_name, last_name) =
+                    (capitalise(parts[0]), capitalise(parts[parts.len() - 1]));
+                parsed.set("first_name", first_name, false);
+                parsed.set("last_name", last_name, false);
+            }
+        }
+    }
+
+    if parsed.contact.organization.is_empty() {
+        let others = rest
+            .iter()
+            .enumerate()
+            .filter(|(at, _)| Some(*at) != name_at)
+            .map(|(at, line)| (at, line.as_str()))
+            .collect::<Vec<(usize, &str)>>();
+        // "Sales Manager at Acme" or "Sales Manager, Acme Inc." name it after the title.
+        let after_title = others.iter().find_map(|(_, line)| {
+            if !is_title(line) {
+                return None;
+            }
+            [" at ", " @ ", ", ", " - ", " – "]
+                .iter()
+                .find_map(|separator| line.split_once(separator))
+                .map(|(_, organization)| organization.trim())
+                .filter(|organization| !organization.is_empty() && !is_title(organization))
+        });
+        if let Some((_, company)) = others.iter().find(|(_, line)| is_company(line)) {
+            parsed.set("organization", company.to_string(), true);
+        } else if let Some(organization) = after_title {
+            let sure = is_company(organization);
+            parsed.set("organization", organization.to_owned(), sure);
+        } else if let Some((_, line)) = others.iter().find(|(at, line)| {
+            name_at.is_some_and(|name_at| *at > name_at)
+                && !is_title(line)
+                && line.split_whitespace().count() <= 6
+        }) {
+            parsed.set("organization", line.to_string(), false);
+        } else if let Some(organization) = domain_organization(&parsed.contact.email) {
+            parsed.set("organization", organization, false);
+        }
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod signature_tests {
+    use super::*;
+
+    #[test]
+    fn parse_signature_reads_labelled_fields() {
+        let parsed = parse_signature(
+            "Best regards,\n\
+            Jane Doe\n\
+            Acme Widgets Inc.\n\
+            T: +1 (555) 010-0200 | F: +1 (555) 010-0299\n\
+            jane.doe@acme.com\n",
+        );
+        assert_eq!(parsed.contact.first_name, "Jane");
+        assert_eq!(parsed.contact.last_name, "Doe");
+        assert_eq!(parsed.contact.email, "jane.doe@acme.com");
+        assert_eq!(parsed.contact.phone, "+1 (555) 010-0200");
+        assert_eq!(parsed.contact.organization, "Acme Widgets Inc.");
+        assert!(!parsed.is_uncertain("email"));
+        assert!(!parsed.is_uncertain("phone"));
+    }
+
+    #[test]
+    fn parse_signature_of_nothing_is_empty() {
+        let parsed = parse_signature("Thanks!\n--\n");
+        assert!(parsed.contact.first_name.is_empty());
+        assert!(parsed.contact.email.is_empty());
+        assert!(parsed.uncertain.is_empty());
+    }
+}
+
+// endregion: SIGNATURE PARSER
+
+// region: SAVED SEARCHES
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, FromRow)]
+pub struct SavedSearch {
+    pub id: i64,
+    #[serde(skip)]
+    pub user_id: i64,
+    pub name: String,
+    pub query: String,
+    pub time_creation: String,
+}
+
+impl SavedSearch {
+    pub async fn list_searches(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+    ) -> anyhow::Result<Vec<SavedSearch>> {
+        let searches_set = sqlx::query_as!(
+            SavedSearch,
+            r#"
+            SELECT *
+            FROM saved_searches_table
+            WHERE user_id = ?1
+            ORDER BY name COLLATE NOCASE
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(searches_set)
+    }
+    pub async fn create_search(&self, pool: Pool<Sqlite>) -> anyhow::Result<u32> {
+        let timestamp_str = get_time();
+        let id_inserted = sqlx::query!(
+            r#"
+            INSERT INTO saved_searches_table ( user_id, name, query, time_creation)
+            VALUES (?1, ?2, ?3, ?4)
+            "#,
+            self.user_id,
+            self.name,
+            self.query,
+            timestamp_str
+        )
+        .execute(&pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id_inserted as u32)
+    }
+    pub async fn delete_search(pool: Pool<Sqlite>, id: u32, user_id: i64) -> anyhow::Result<u32> {
+        let rows_affected = sqlx::query!(
+            r#"
+            DELETE FROM saved_searches_table
+            WHERE id = ?1 AND user_id = ?2
+            "#,
+            id,
+            user_id
+        )
+        .execute(&pool)
+        .await?
+        .rows_affected();
+        Ok(rows_affected as u32)
+    }
+    /// Parses the stored query string back into the `/contacts/show` parameters.
+    pub fn params(&self) -> anyhow::Result<ShowParams> {
+        Ok(serde_urlencoded::from_str::<ShowParams>(&self.query)?)
+    }
+    /// How many contacts each search finds. Plain ones are counted by SQL;
+    /// text searches (typo-tolerant) and birthday windows are worked out from
+    /// one read of the table, only when some search needs it.
+    pub async fn counts(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        searches: &[SavedSearch],
+    ) -> anyhow::Result<Vec<u32>> {
+        let mut conn = pool.acquire().await?;
+        let mut contacts_set = None;
+        let mut counts = Vec::new();
+        for search in searches {
+            let params = search.params()?;
+            let search_bar = params.search_p.as_deref().unwrap_or("");
+            if search_bar.is_empty() && params.birthday_p == 0 {
+                counts.push(Contacts::count_owned(&mut conn, user_id).await?);
+                continue;
+            }
+            let contacts_set = match contacts_set.as_ref() {
+                Some(contacts_set) => contacts_set,
+                None => {
+                    contacts_set.insert(Contacts::owned_contacts(&mut conn, user_id, -1, 0).await?)
+                }
+            };
+            let view = ContactsView::new(
+                contacts_set.clone(),
+                search_bar,
+                params.birthday_p,
+                "",
+                birthday_window(params.window_p),
+            );
+            counts.push(view.contacts.len() as u32);
+        }
+        Ok(counts)
+    }
+}
+
+// endregion: SAVED SEARCHES
+
+// region: ARCHIVER
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chacha20poly1305::aead::{Aead, AeadCore, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Marks archive files, so a restore can tell them from any other JSON.
+pub const ARCHIVE_FORMAT: &str = "contacts-archive";
+/// How long a signed download link stays valid, in seconds.
+pub const ARCHIVE_LINK_SECONDS: i64 = 300;
+
+/// One archive a user asked for, kept in the database so the list of past
+/// archives, and jobs cut short by a restart, survive the process.
+#[derive(Debug, Clone, Default, FromRow)]
+pub struct ArchiveJob {
+    pub id: i64,
+    pub user_id: i64,
+    /// "Running", "Complete", "Failed" or "Cancelled".
+    pub status: String,
+    pub progress: f64,
+    pub file_name: String,
+    /// Bytes on disk once complete.
+    pub size: i64,
+    /// Hidden from the header widget; still listed on the archives page.
+    pub dismissed: bool,
+    pub time_creation: String,
+}
+
+impl ArchiveJob {
+    pub fn path(&self) -> PathBuf {
+        archive_dir().join(&self.file_name)
+    }
+    /// Where the job writes until it is complete.
+    pub fn partial_path(&self) -> PathBuf {
+        archive_dir().join(format!("{}.part", self.file_name))
+    }
+    pub fn size_label(&self) -> String {
+        format_size(self.size)
+    }
+    pub fn encrypted(&self) -> bool {
+        self.file_name.ends_with(ENCRYPTED_ARCHIVE_EXTENSION)
+    }
+    /// Starts a job for the user, unless one is already running; returns it.
+    pub async fn start(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        encrypted: bool,
+    ) -> anyhow::Result<(ArchiveJob, bool)> {
+        let timestamp_str = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        loop {
+            let mut tx = pool.begin().await?;
+            // archive_jobs_running_idx allows one running job per user, so two
+            // requests at once cannot both start one.
+            let inserted = sqlx::query!(
+                r#"
+                INSERT INTO archive_jobs_table (user_id, status, file_name, time_creation)
+                VALUES (?1, 'Running', '', ?2)
+                "#,
+                user_id,
+                timestamp_str
+            )
+            .execute(&mut *tx)
+            .await;
+            let id_inserted = match inserted {
+                Ok(result) => result.last_insert_rowid(),
+                Err(error) if error.to_string().contains("UNIQUE") => {
+                    tx.rollback().await?;
+                    match Self::running(pool, user_id).await? {
+                        Some(job) => return Ok((job, false)),
+                        // It finished in between; try again.
+                        None => continue,
+                    }
+                }
+                Err(error) => return Err(error.into()),
+            };
+            let file_name = archive_file_name(user_id, Some(id_inserted), encrypted);
+            sqlx::query!(
+                r#"
+                UPDATE archive_jobs_table
+                SET file_name = ?1
+                WHERE id = ?2
+                "#,
+                file_name,
+                id_inserted
+            )
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            let job = Self::get(pool, id_inserted, user_id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("archive job {} vanished", id_inserted))?;
+            return Ok((job, true));
+        }
+    }
+    /// The user's running job, dismissed or not.
+    pub async fn running(pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<Option<ArchiveJob>> {
+        let job = sqlx::query_as!(
+            ArchiveJob,
+            r#"
+            SELECT *
+            FROM archive_jobs_table
+            WHERE user_id = ?1 AND status = 'Running'
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(job)
+    }
+    pub async fn get(
+        pool: &Pool<Sqlite>,
+        id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<Option<ArchiveJob>> {
+        let job = sqlx::query_as!(
+            ArchiveJob,
+            r#"
+            SELECT *
+            FROM archive_jobs_table
+            WHERE id = ?1 AND user_id = ?2
+            "#,
+            id,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(job)
+    }
+    /// The job the header widget shows: the user's newest one not dismissed.
+    pub async fn latest(pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<Option<ArchiveJob>> {
+        let job = sqlx::query_as!(
+            ArchiveJob,
+            r#"
+            SELECT *
+            FROM archive_jobs_table
+            WHERE user_id = ?1 AND NOT dismissed
+            ORDER BY id DESC
+            LIMIT 1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(job)
+    }
+    pub async fn list(pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<Vec<ArchiveJob>> {
+        let jobs = sqlx::query_as!(
+            ArchiveJob,
+            r#"
+            SELECT *
+            FROM archive_jobs_table
+            WHERE user_id = ?1
+            ORDER BY id DESC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(jobs)
+    }
+    /// Records progress; returns false once the job is no longer running,
+    /// which is how a cancelled job learns to stop.
+    pub async fn set_progress(pool: &Pool<Sqlite>, id: i64, progress: f64) -> anyhow::Result<bool> {
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE archive_jobs_table
+            SET progress = ?1
+            WHERE id = ?2 AND status = 'Running'
+            "#,
+            progress,
+            id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+        Ok(rows_affected > 0)
+    }
+    /// Ends a running job; returns false if it was cancelled meanwhile.
+    pub async fn finish(
+        pool: &Pool<Sqlite>,
+        id: i64,
+        status: &str,
+        size: i64,
+    ) -> anyhow::Result<bool> {
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE archive_jobs_table
+            SET status = ?1, size = ?2, progress = 1
+            WHERE id = ?3 AND status = 'Running'
+            "#,
+            status,
+            size,
+            id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+        Ok(rows_affected > 0)
+    }
+    /// Asks the user's running job to stop; the job removes its partial file
+    /// when it next reports progress.
+    pub async fn cancel(pool: &Pool<Sqlite>, id: i64, user_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE archive_jobs_table
+            SET status = 'Cancelled'
+            WHERE id = ?1 AND user_id = ?2 AND status = 'Running'
+            "#,
+            id,
+            user_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+    /// Hides the user's finished jobs from the header widget.
+    pub async fn dismiss(pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE archive_jobs_table
+            SET dismissed = TRUE
+            WHERE user_id = ?1 AND status != 'Running'
+            "#,
+            user_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+    /// Removes a finished job and its file.
+    pub async fn delete(&self, pool: &Pool<Sqlite>) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM archive_jobs_table
+            WHERE id = ?1 AND status != 'Running'
+            "#,
+            self.id
+        )
+        .execute(pool)
+        .await?;
+        if let Err(error) = tokio::fs::remove_file(self.path()).await {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                return Err(error.into());
+            }
+        }
+        Ok(())
+    }
+    /// Jobs still "Running" at startup died with the previous process: their
+    /// partial files are removed and they are marked failed.
+    pub async fn fail_interrupted(pool: &Pool<Sqlite>) -> anyhow::Result<()> {
+        let jobs = sqlx::query_as!(
+            ArchiveJob,
+            r#"
+            SELECT *
+            FROM archive_jobs_table
+            WHERE status = 'Running'
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+        for job in jobs {
+            let _ = tokio::fs::remove_file(job.partial_path()).await;
+            Self::finish(pool, job.id, "Failed", 0).await?;
+        }
+        Ok(())
+    }
+}
+
+/// One user's contacts and saved searches, independent of the database file:
+/// no password hashes, sessions or other users' data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactsArchive {
+    pub format: String,
+    /// The latest migration applied when the archive was written.
+    pub schema_version: i64,
+    pub created: String,
+    pub username: String,
+    pub contacts: Vec<Contact>,
+    pub saved_searches: Vec<SavedSearch>,
+}
+
+/// A file size for people: bytes, KB or MB.
+pub fn format_size(size: i64) -> String {
+    match size {
+        size if size < 1024 => format!("{} B", size),
+        size if size < 1024 * 1024 => format!("{:.1} KB", size as f64 / 1024.0),
+        size => format!("{:.1} MB", size as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// Version of the latest migration in `migrations/` applied to the database.
+pub async fn schema_version(pool: &Pool<Sqlite>) -> anyhow::Result<i64> {
+    // sqlx creates this table at runtime, so the query macros cannot check it.
+    let version: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(MAX(version), 0) FROM _sqlx_migrations WHERE success = 1",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(version)
+}
+
+/// Where archives are written: `ARCHIVE_DIR`, or `db/archives` next to the database.
+pub fn archive_dir() -> PathBuf {
+    std::env::var("ARCHIVE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("db/archives"))
+}
+
+/// A new, timestamped archive name such as `contacts-3-20240525-143000-12.json`
+/// for job 12, with `.enc` appended when it is encrypted. The job id keeps
+/// archives started in the same second apart.
+pub fn archive_file_name(user_id: i64, job_id: Option<i64>, encrypted: bool) -> String {
+    format!(
+        "contacts-{}-{}{}.json{}",
+        user_id,
+        Local::now().format("%Y%m%d-%H%M%S"),
+        job_id
+            .map(|job_id| format!("-{}", job_id))
+            .unwrap_or_default(),
+        if encrypted {
+            ENCRYPTED_ARCHIVE_EXTENSION
+        } else {
+            ""
+        }
+    )
+}
+
+/// Reads the user's contacts in chunks inside one transaction, so the archive
+/// is a consistent snapshot, and writes it as JSON to `destination`.
+async fn write_archive(
+    job_id: i64,
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    username: &str,
+    destination: &Path,
+    passphrase: Option<String>,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    let total = Contacts::count_owned(&mut tx, user_id).await?;
+    let mut contacts_set = Vec::new();
+    loop {
+        let offset = contacts_set.len() as i64;
+        let chunk = Contacts::owned_contacts(&mut tx, user_id, EXPORT_CHUNK, offset).await?;
+        if chunk.is_empty() {
+            break;
+        }
+        contacts_set.extend(chunk);
+        let progress = 0.9 * contacts_set.len() as f64 / total.max(1) as f64;
+        if !ArchiveJob::set_progress(pool, job_id, progress).await? {
+            anyhow::bail!("cancelled");
+        }
+    }
+    tx.commit().await?;
+    let archive = ContactsArchive {
+        format: ARCHIVE_FORMAT.to_owned(),
+        schema_version: schema_version(pool).await?,
+        created: Local::now().to_rfc3339(),
+        username: username.to_owned(),
+        contacts: contacts_set,
+        saved_searches: SavedSearch::list_searches(pool, user_id).await?,
+    };
+    let mut content = serde_json::to_vec_pretty(&archive)?;
+    if let Some(passphrase) = passphrase {
+        content = task::spawn_blocking(move || encrypt_archive(&content, &passphrase)).await??;
+    }
+    tokio::fs::create_dir_all(archive_dir()).await?;
+    tokio::fs::write(destination, content).await?;
+    Ok(())
+}
+
+/// Runs an archive job, writing to a `.part` file that is only renamed once
+/// it is complete; a cancelled job leaves no file behind.
+pub async fn run_thread(
+    pool: Pool<Sqlite>,
+    job: ArchiveJob,
+    username: String,
+    passphrase: Option<String>,
+) {
+    let partial = job.partial_path();
+    let mut result =
+        write_archive(job.id, &pool, job.user_id, &username, &partial, passphrase).await;
+    if result.is_ok() {
+        result = tokio::fs::rename(&partial, job.path())
+            .await
+            .map_err(anyhow::Error::from);
+    }
+    let finished = match result {
+        Ok(()) => {
+            let size = tokio::fs::metadata(job.path())
+                .await
+                .map(|metadata| metadata.len() as i64)
+                .unwrap_or(0);
+            let finished = ArchiveJob::finish(&pool, job.id, "Complete", size).await;
+            if let Ok(false) = finished {
+                // Cancelled after the last chunk was read.
+                let _ = tokio::fs::remove_file(job.path()).await;
+            }
+            finished
+        }
+        Err(error) => {
+            println!("->> {} - ARCHIVE FAILED: {}", get_time(), error);
+            let _ = tokio::fs::remove_file(&partial).await;
+            ArchiveJob::finish(&pool, job.id, "Failed", 0).await
+        }
+    };
+    if let Err(error) = finished {
+        println!("->> {} - ARCHIVE NOT RECORDED: {}", get_time(), error);
+    }
+}
+
+/// Appended to the names of encrypted archives.
+pub const ENCRYPTED_ARCHIVE_EXTENSION: &str = ".enc";
+/// Starts every encrypted archive; it is followed by the argon2 salt, the
+/// passphrase check, the nonce and the XChaCha20-Poly1305 ciphertext.
+const ENCRYPTED_ARCHIVE_MAGIC: &[u8] = b"contacts-archive-enc-v1\n";
+const ENCRYPTED_ARCHIVE_SALT: usize = 16;
+const ENCRYPTED_ARCHIVE_CHECK: usize = 32;
+const ENCRYPTED_ARCHIVE_NONCE: usize = 24;
+
+/// Why an archive could not be opened, worded for the restore page.
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveCryptError {
+    #[error("This archive is encrypted, enter its passphrase.")]
+    PassphraseMissing,
+    #[error("Wrong passphrase for this archive.")]
+    WrongPassphrase,
+    #[error("This archive was modified or damaged after it was written.")]
+    Tampered,
+    #[error("The archive key could not be derived: {0}")]
+    Kdf(String),
+    #[error("The archive could not be encrypted.")]
+    Encrypt,
+}
+
+/// Stretches the passphrase with argon2id into the encryption key and a
+/// separate value that tells a wrong passphrase from a tampered file.
+fn archive_keys(
+    passphrase: &str,
+    salt: &[u8],
+) -> Result<([u8; 32], [u8; ENCRYPTED_ARCHIVE_CHECK]), ArchiveCryptError> {
+    let mut output = [0u8; 32 + ENCRYPTED_ARCHIVE_CHECK];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut output)
+        .map_err(|error| ArchiveCryptError::Kdf(error.to_string()))?;
+    let mut key = [0u8; 32];
+    let mut check = [0u8; ENCRYPTED_ARCHIVE_CHECK];
+    key.copy_from_slice(&output[..32]);
+    check.copy_from_slice(&output[32..]);
+    Ok((key, check))
+}
+
+/// Encrypts an archive with a key derived from the passphrase. The header is
+/// authenticated along with the content.
+pub fn encrypt_archive(content: &[u8], passphrase: &str) -> Result<Vec<u8>, ArchiveCryptError> {
+    let salt: [u8; ENCRYPTED_ARCHIVE_SALT] = rand::random();
+    let (key, check) = archive_keys(passphrase, &salt)?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut header = ENCRYPTED_ARCHIVE_MAGIC.to_vec();
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&check);
+    header.extend_from_slice(&nonce);
+    let ciphertext = <XChaCha20Poly1305 as chacha20poly1305::KeyInit>::new(&key.into())
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: content,
+                aad: &header,
+            },
+        )
+        .map_err(|_| ArchiveCryptError::Encrypt)?;
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+pub fn is_encrypted_archive(content: &[u8]) -> bool {
+    content.starts_with(ENCRYPTED_ARCHIVE_MAGIC)
+}
+
+/// The archive JSON of an uploaded file, decrypted when it is encrypted.
+pub fn open_archive(content: &[u8], passphrase: &str) -> Result<Vec<u8>, ArchiveCryptError> {
+    if !is_encrypted_archive(content) {
+        return Ok(content.to_vec());
+    }
+    if passphrase.is_empty() {
+        return Err(ArchiveCryptError::PassphraseMissing);
+    }
+    let header_len = ENCRYPTED_ARCHIVE_MAGIC.len()
+        + ENCRYPTED_ARCHIVE_SALT
+        + ENCRYPTED_ARCHIVE_CHECK
+        + ENCRYPTED_ARCHIVE_NONCE;
+    if content.len() < header_len {
+        return Err(ArchiveCryptError::Tampered);
+    }
+    let (header, ciphertext) = content.split_at(header_len);
+    let (salt, rest) = header[ENCRYPTED_ARCHIVE_MAGIC.len()..].split_at(ENCRYPTED_ARCHIVE_SALT);
+    let (check, nonce) = rest.split_at(ENCRYPTED_ARCHIVE_CHECK);
+    let (key, expected_check) = archive_keys(passphrase, salt)?;
+    if check != expected_check {
+        return Err(ArchiveCryptError::WrongPassphrase);
+    }
+    <XChaCha20Poly1305 as chacha20poly1305::KeyInit>::new(&key.into())
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| ArchiveCryptError::Tampered)
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// `ARCHIVE_LINK_SECRET`, or a random key that lasts until the next restart.
+fn archive_link_key() -> &'static [u8] {
+    static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+    KEY.get_or_init(|| match std::env::var("ARCHIVE_LINK_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+        _ => rand::random::<[u8; 32]>().to_vec(),
+    })
+}
+
+/// A short-lived download link for one archive, signed with HMAC-SHA256 so it
+/// works without a session but cannot be forged or reused for other files.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ArchiveLink {
+    pub user_p: i64,
+    pub file_p: String,
+    pub expires_p: i64,
+    pub signature_p: String,
+}
+
+impl ArchiveLink {
+    fn mac(user_id: i64, file_name: &str, expires: i64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(archive_link_key()).expect("HMAC takes keys of any size");
+        mac.update(format!("{}\n{}\n{}", user_id, file_name, expires).as_bytes());
+        mac
+    }
+    pub fn sign(user_id: i64, file_name: &str) -> ArchiveLink {
+        let expires = Local::now().timestamp() + ARCHIVE_LINK_SECONDS;
+        let signature = Self::mac(user_id, file_name, expires)
+            .finalize()
+            .into_bytes();
+        ArchiveLink {
+            user_p: user_id,
+            file_p: file_name.to_owned(),
+            expires_p: expires,
+            signature_p: URL_SAFE_NO_PAD.encode(signature),
+        }
+    }
+    pub fn url(&self) -> anyhow::Result<String> {
+        Ok(format!(
+            "/contacts/archive/download?{}",
+            serde_urlencoded::to_string(self)?
+        ))
+    }
+    /// True while the link has not expired, its signature matches and it
+    /// names a plain file of `archive_dir()`.
+    pub fn verify(&self) -> bool {
+        let plain_name = self.file_p.starts_with("contacts-")
+            && !self.file_p.contains(['/', '\\'])
+            && !self.file_p.contains("..");
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(&self.signature_p) else {
+            return false;
+        };
+        plain_name
+            && self.expires_p >= Local::now().timestamp()
+            && Self::mac(self.user_p, &self.file_p, self.expires_p)
+                .verify_slice(&signature)
+                .is_ok()
+    }
+    pub fn path(&self) -> PathBuf {
+        archive_dir().join(&self.file_p)
+    }
+    /// Adds the download to the audit log.
+    pub async fn record_download(
+        &self,
+        pool: &Pool<Sqlite>,
+        user_agent: &str,
+    ) -> anyhow::Result<()> {
+        let timestamp_str = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        sqlx::query!(
+            r#"
+            INSERT INTO archive_downloads_table (user_id, username, file_name, user_agent, time_download)
+            SELECT ?1, COALESCE((SELECT username FROM users_table WHERE id = ?1), ''), ?2, ?3, ?4
+            "#,
+            self.user_p,
+            self.file_p,
+            user_agent,
+            timestamp_str
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod archiver_tests {
+    use super::*;
+
+    const ARCHIVE: &[u8] = br#"{"username":"tester","contacts":[]}"#;
+
+    #[test]
+    fn open_archive_round_trips() {
+        let sealed = encrypt_archive(ARCHIVE, "correct horse").unwrap();
+        assert!(is_encrypted_archive(&sealed));
+        assert_eq!(open_archive(&sealed, "correct horse").unwrap(), ARCHIVE);
+    }
+
+    #[test]
+    fn open_archive_passes_plain_archives_through() {
+        assert_eq!(open_archive(ARCHIVE, "").unwrap(), ARCHIVE);
+        assert_eq!(open_archive(ARCHIVE, "unused").unwrap(), ARCHIVE);
+    }
+
+    #[test]
+    fn open_archive_tells_wrong_passphrase_from_tampering() {
+        let sealed = encrypt_archive(ARCHIVE, "correct horse").unwrap();
+        assert!(matches!(
+            open_archive(&sealed, ""),
+            Err(ArchiveCryptError::PassphraseMissing)
+        ));
+        assert!(matches!(
+            open_archive(&sealed, "wrong horse"),
+            Err(ArchiveCryptError::WrongPassphrase)
+        ));
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            open_archive(&tampered, "correct horse"),
+            Err(ArchiveCryptError::Tampered)
+        ));
+        let truncated = &sealed[..ENCRYPTED_ARCHIVE_MAGIC.len() + 4];
+        assert!(matches!(
+            open_archive(truncated, "correct horse"),
+            Err(ArchiveCryptError::Tampered)
+        ));
+    }
+}
+
+// endregion: ARCHIVER
+
+// region: BACKUPS
+
+use croner::Cron;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection};
+
+/// Scheduled and manual backups never run at the same time.
+static BACKUP_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// One snapshot of the whole database file, as recorded after writing it.
+#[derive(Debug, Clone, Default, FromRow)]
+pub struct Backup {
+    pub id: i64,
+    pub file_name: String,
+    /// "scheduled", "manual" or "restore", for the safety copy taken before one.
+    pub origin: String,
+    /// "Ok" once `PRAGMA integrity_check` passed, "Failed" otherwise.
+    pub status: String,
+    /// Why the backup failed; empty when it is ok.
+    pub message: String,
+    pub size: i64,
+    pub time_creation: String,
+}
+
+/// How backups are taken and kept, read from the environment:
+/// `BACKUP_SCHEDULE` (a cron expression, no scheduled backups when unset),
+/// `BACKUP_KEEP_DAILY`, `BACKUP_KEEP_WEEKLY` and `BACKUP_KEEP_MONTHLY`.
+#[derive(Debug, Clone)]
+pub struct BackupPolicy {
+    pub schedule: Option<String>,
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+    pub keep_monthly: usize,
+}
+
+impl BackupPolicy {
+    pub fn from_env() -> anyhow::Result<BackupPolicy> {
+        fn keep(name: &str, default: usize) -> anyhow::Result<usize> {
+            match std::env::var(name) {
+                Ok(value) => value
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("{} must be a number, not {:?}", name, value)),
+                Err(_) => Ok(default),
+            }
+        }
+        let schedule = std::env::var("BACKUP_SCHEDULE")
+            .ok()
+            .map(|schedule| schedule.trim().to_owned())
+            .filter(|schedule| !schedule.is_empty());
+        let policy = BackupPolicy {
+            schedule,
+            keep_daily: keep("BACKUP_KEEP_DAILY", 7)?,
+            keep_weekly: keep("BACKUP_KEEP_WEEKLY", 4)?,
+            keep_monthly: keep("BACKUP_KEEP_MONTHLY", 12)?,
+        };
+        policy.cron()?;
+        Ok(policy)
+    }
+    pub fn cron(&self) -> anyhow::Result<Option<Cron>> {
+        let Some(schedule) = &self.schedule else {
+            return Ok(None);
+        };
+        let cron = Cron::new(schedule)
+            .parse()
+            .map_err(|error| anyhow::anyhow!("BACKUP_SCHEDULE {:?}: {}", schedule, error))?;
+        Ok(Some(cron))
+    }
+    /// When the next scheduled backup runs, if any.
+    pub fn next_run(&self) -> Option<DateTime<Local>> {
+        let cron = self.cron().ok()??;
+        cron.find_next_occurrence(&Local::now(), false).ok()
+    }
+}
+
+/// Where backups are written: `BACKUP_DIR`, or `db/backups` next to the database.
+pub fn backup_dir() -> PathBuf {
+    std::env::var("BACKUP_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("db/backups"))
+}
+
+impl Backup {
+    pub fn path(&self) -> PathBuf {
+        backup_dir().join(&self.file_name)
+    }
+    pub fn size_label(&self) -> String {
+        format_size(self.size)
+    }
+    pub async fn list(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<Backup>> {
+        let backups = sqlx::query_as!(
+            Backup,
+            r#"
+            SELECT *
+            FROM backups_table
+            ORDER BY time_creation DESC, id DESC
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(backups)
+    }
+    async fn record(&self, pool: &Pool<Sqlite>) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO backups_table (file_name, origin, status, message, size, time_creation)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+            self.file_name,
+            self.origin,
+            self.status,
+            self.message,
+            self.size,
+            self.time_creation
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+    async fn delete(&self, pool: &Pool<Sqlite>) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM backups_table
+            WHERE id = ?1
+            "#,
+            self.id
+        )
+        .execute(pool)
+        .await?;
+        if let Err(error) = tokio::fs::remove_file(self.path()).await {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                return Err(error.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Opens the copy on its own, read only, and checks it is a sound database.
+async fn check_backup(path: &Path) -> anyhow::Result<()> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect()
+        .await?;
+    // Pragmas are not described by the query macros.
+    let report: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut conn)
+        .await?;
+    conn.close().await?;
+    if report != ["ok"] {
+        anyhow::bail!("integrity check: {}", report.join("; "));
+    }
+    Ok(())
+}
+
+/// Writes a consistent copy of the live database with `VACUUM INTO`, checks
+/// it, records the outcome and applies the retention policy.
+pub async fn run_backup(
+    pool: &Pool<Sqlite>,
+    policy: &BackupPolicy,
+    origin: &str,
+) -> anyhow::Result<Backup> {
+    let _guard = BACKUP_LOCK.lock().await;
+    let now = Local::now();
+    let mut backup = Backup {
+        file_name: format!("backup-{}.db", now.format("%Y%m%d-%H%M%S-%3f")),
+        origin: origin.to_owned(),
+        status: "Ok".to_owned(),
+        time_creation: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ..Default::default()
+    };
+    let path = backup.path();
+    // VACUUM INTO refuses to overwrite, and a failure must not remove a good copy.
+    if tokio::fs::try_exists(&path).await? {
+        anyhow::bail!("backup {} already exists", backup.file_name);
+    }
+    let written = async {
+        tokio::fs::create_dir_all(backup_dir()).await?;
+        // VACUUM INTO is a statement the query macros cannot check.
+        sqlx::query("VACUUM INTO ?1")
+            .bind(path.to_string_lossy().into_owned())
+            .execute(pool)
+            .await?;
+        check_backup(&path).await?;
+        anyhow::Ok(tokio::fs::metadata(&path).await?.len() as i64)
+    }
+    .await;
+    match written {
+        Ok(size) => backup.size = size,
+        Err(error) => {
+            println!("->> {} - BACKUP FAILED: {}", get_time(), error);
+            let _ = tokio::fs::remove_file(&path).await;
+            backup.status = "Failed".to_owned();
+            backup.message = error.to_string();
+        }
+    }
+    backup.record(pool).await?;
+    prune_backups(pool, policy).await?;
+    Ok(backup)
+}
+
+/// Grandfather-father-son retention: of the good backups, newest first, the
+/// first one of each of the last `keep_daily` days, `keep_weekly` ISO weeks
+/// and `keep_monthly` months is kept. Failed entries older than the oldest
+/// kept backup go too.
+pub async fn prune_backups(pool: &Pool<Sqlite>, policy: &BackupPolicy) -> anyhow::Result<()> {
+    let backups = Backup::list(pool).await?;
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    let mut months = HashSet::new();
+    let mut kept = HashSet::new();
+    for backup in backups.iter().filter(|backup| backup.status == "Ok") {
+        let Ok(time) =
+            chrono::NaiveDateTime::parse_from_str(&backup.time_creation, "%Y-%m-%d %H:%M:%S")
+        else {
+            kept.insert(backup.id);
+            continue;
+        };
+        let date = time.date();
+        let week = date.iso_week();
+        let mut keep = false;
+        if days.len() < policy.keep_daily && days.insert(date) {
+            keep = true;
+        }
+        if weeks.len() < policy.keep_weekly && weeks.insert((week.year(), week.week())) {
+            keep = true;
+        }
+        if months.len() < policy.keep_monthly && months.insert((date.year(), date.month())) {
+            keep = true;
+        }
+        if keep {
+            kept.insert(backup.id);
+        }
+    }
+    let oldest_kept = backups
+        .iter()
+        .filter(|backup| kept.contains(&backup.id))
+        .map(|backup| backup.time_creation.clone())
+        .min()
+        .unwrap_or_default();
+    for backup in backups {
+        let failed_and_recent = backup.status != "Ok" && backup.time_creation > oldest_kept;
+        if !kept.contains(&backup.id) && !failed_and_recent {
+            backup.delete(pool).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Takes a backup at every occurrence of `BACKUP_SCHEDULE`, until the process ends.
+pub async fn run_backup_schedule(pool: Pool<Sqlite>, policy: BackupPolicy) {
+    let Ok(Some(cron)) = policy.cron() else {
+        return;
+    };
+    loop {
+        let now = Local::now();
+        let Ok(next) = cron.find_next_occurrence(&now, false) else {
+            println!("->> {} - BACKUP SCHEDULE: no next occurrence", get_time());
+            return;
+        };
+        let wait = (next - now).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+        if let Err(error) = run_backup(&pool, &policy, "scheduled").await {
+            println!("->> {} - BACKUP NOT RECORDED: {}", get_time(), error);
+        }
+    }
+}
+
+/// Whether the user may see the backups: named in the comma separated `ADMIN_USERS`.
+pub fn is_admin(username: &str) -> bool {
+    std::env::var("ADMIN_USERS")
+        .map(|admins| admins.split(',').any(|admin| admin.trim() == username))
+        .unwrap_or(false)
+}
+
+// endregion: BACKUPS
+
+// region: RESTORE
+
+/// Whether a restore replaces the user's contacts and saved searches or adds
+/// the ones missing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    #[default]
+    Merge,
+    Replace,
+}
+
+/// What an uploaded archive holds and what restoring it would do.
+#[derive(Debug, Clone, Default)]
+pub struct RestoreSummary {
+    pub username: String,
+    pub created: String,
+    pub schema_version: i64,
+    pub contacts: usize,
+    pub saved_searches: usize,
+    /// Oldest and newest creation date of the archived contacts.
+    pub first_created: String,
+    pub last_created: String,
+    /// Archived contacts a merge or a replace would write; the others are
+    /// incomplete or have an email one of the user's contacts already uses.
+    pub merged: usize,
+    pub replaced: usize,
+    /// The user's contacts now; replace removes them.
+    pub owned: usize,
+}
+
+/// Versions of the migrations in `migrations/` this build knows about.
+fn known_schema_versions() -> Vec<i64> {
+    sqlx::migrate!("./migrations")
+        .migrations
+        .iter()
+        .map(|migration| migration.version)
+        .collect()
+}
+
+/// Emails compare like `Contact::validate_email`: trimmed and ignoring case.
+fn email_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// The emails of the user's contacts; other accounts' contacts never block
+/// a restore.
+async fn taken_emails(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+) -> anyhow::Result<HashSet<String>> {
+    let emails = sqlx::query_scalar!(
+        r#"
+        SELECT email
+        FROM contacts_table
+        WHERE user_id = ?1
+        "#,
+        user_id
+    )
+    .fetch_all(conn)
+    .await?
+    .iter()
+    .map(|email| email_key(email))
+    .collect();
+    Ok(emails)
+}
+
+impl ContactsArchive {
+    /// Reads an uploaded archive and checks it was written by this app, for
+    /// this user, against a schema the database can take.
+    pub async fn parse(
+        bytes: &[u8],
+        pool: &Pool<Sqlite>,
+        username: &str,
+    ) -> anyhow::Result<ContactsArchive> {
+        let archive: ContactsArchive = serde_json::from_slice(bytes)
+            .map_err(|error| anyhow::anyhow!("This is not a contacts archive: {}", error))?;
+        if archive.format != ARCHIVE_FORMAT {
+            anyhow::bail!("This is not a contacts archive made by this app.");
+        }
+        if !archive.username.eq_ignore_ascii_case(username) {
+            anyhow::bail!(
+                "This archive belongs to {}; only that account can restore it.",
+                archive.username
+            );
+        }
+        if !known_schema_versions().contains(&archive.schema_version) {
+            anyhow::bail!(
+                "The archive's schema version {} matches no migration of this app; it may come from a newer release.",
+                archive.schema_version
+            );
+        }
+        let current = schema_version(pool).await?;
+        if archive.schema_version > current {
+            anyhow::bail!(
+                "The archive's schema version {} is newer than the database's ({}).",
+                archive.schema_version,
+                current
+            );
+        }
+        Ok(archive)
+    }
+    /// The archived contacts a restore writes: complete ones whose email is
+    /// not taken yet, neither by the user's contacts nor earlier in the archive.
+    fn restorable(&self, mut taken: HashSet<String>) -> Vec<&Contact> {
+        self.contacts
+            .iter()
+            .filter(|contact| contact.required_errors().messages().is_empty())
+            .filter(|contact| taken.insert(email_key(&contact.email)))
+            .collect()
+    }
+    pub async fn summary(
+        &self,
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+    ) -> anyhow::Result<RestoreSummary> {
+        let mut conn = pool.acquire().await?;
+        let owned = Contacts::count_owned(&mut conn, user_id).await?;
+        let merged = self
+            .restorable(taken_emails(&mut conn, user_id).await?)
+            .len();
+        // Replace removes the user's contacts first, so nothing is taken.
+        let replaced = self.restorable(HashSet::new()).len();
+        let created = self
+            .contacts
+            .iter()
+            .map(|contact| contact.time_creation.as_str());
+        Ok(RestoreSummary {
+            username: self.username.clone(),
+            created: self.created.clone(),
+            schema_version: self.schema_version,
+            contacts: self.contacts.len(),
+            saved_searches: self.saved_searches.len(),
+            first_created: created.clone().min().unwrap_or("").to_owned(),
+            last_created: created.max().unwrap_or("").to_owned(),
+            merged,
+            replaced,
+            owned: owned as usize,
+        })
+    }
+    /// Writes the archive back as the user's data in one transaction, keeping
+    /// creation times and skipping the contacts `restorable` leaves out;
+    /// returns how many contacts were restored.
+    pub async fn restore(
+        &self,
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        mode: RestoreMode,
+    ) -> anyhow::Result<usize> {
+        let mut tx = pool.begin().await?;
+        if mode == RestoreMode::Replace {
+            sqlx::query!(
+                r#"
+                DELETE FROM contacts_table
+                WHERE user_id = ?1
+                "#,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                r#"
+                DELETE FROM saved_searches_table
+                WHERE user_id = ?1
+                "#,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        let taken = taken_emails(&mut tx, user_id).await?;
+        let mut restored = 0;
+        for contact in self.restorable(taken) {
+            sqlx::query!(
+                r#"
+                INSERT INTO contacts_table ( first_name, last_name, phone, email, birth_date, time_creation, user_id, address, organization)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                "#,
+                contact.first_name,
+                contact.last_name,
+                contact.phone,
+                contact.email,
+                contact.birth_date,
+                contact.time_creation,
+                user_id,
+                contact.address,
+                contact.organization
+            )
+            .execute(&mut *tx)
+            .await?;
+            restored += 1;
+        }
+        let mut search_names = sqlx::query_scalar!(
+            r#"
+            SELECT name
+            FROM saved_searches_table
+            WHERE user_id = ?1
+            "#,
+            user_id
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect::<HashSet<String>>();
+        for search in &self.saved_searches {
+            if !search_names.insert(search.name.clone()) {
+                continue;
+            }
+            sqlx::query!(
+                r#"
+                INSERT INTO saved_searches_table ( user_id, name, query, time_creation)
+                VALUES (?1, ?2, ?3, ?4)
+                "#,
+                user_id,
+                search.name,
+                search.query,
+                search.time_creation
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(restored)
+    }
+}
+
+// endregion: RESTORE
+
+// region: CALENDAR
+
+/// Year used for the first occurrence of birthdays stored without a year; a
+/// leap year, so February 29 exists.
+const CALENDAR_NO_YEAR_START: i32 = 2000;
+
+/// Where the app is reached from outside, for addresses handed to other
+/// programs such as the birthday feed: `BASE_URL`, or `http://localhost:8080`.
+pub fn base_url() -> anyhow::Result<String> {
+    let base = std::env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:8080".to_owned());
+    let base = base.trim().trim_end_matches('/');
+    if !(base.starts_with("http://") || base.starts_with("https://")) {
+        anyhow::bail!(
+            "BASE_URL must start with http:// or https://, not {:?}",
+            base
+        );
+    }
+    Ok(base.to_owned())
+}
+
+/// The secret in a user's birthday feed URL. Regenerating it replaces the old
+/// one, so subscriptions with the previous URL stop working.
+pub struct CalendarToken;
+
+impl CalendarToken {
+    pub async fn get(pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<Option<String>> {
+        let token = sqlx::query_scalar!(
+            r#"
+            SELECT token
+            FROM calendar_tokens_table
+            WHERE user_id = ?1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(token)
+    }
+    pub async fn regenerate(pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<String> {
+        let token = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+        let timestamp_str = get_time();
+        sqlx::query!(
+            r#"
+            INSERT INTO calendar_tokens_table (user_id, token, time_creation)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT (user_id) DO UPDATE SET token = ?2, time_creation = ?3
+            "#,
+            user_id,
+            token,
+            timestamp_str
+        )
+        .execute(pool)
+        .await?;
+        Ok(token)
+    }
+    pub async fn revoke(pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM calendar_tokens_table
+            WHERE user_id = ?1
+            "#,
+            user_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+    /// The user a feed URL belongs to, if the token is current.
+    pub async fn user_id(pool: &Pool<Sqlite>, token: &str) -> anyhow::Result<Option<i64>> {
+        let user_id = sqlx::query_scalar!(
+            r#"
+            SELECT user_id
+            FROM calendar_tokens_table
+            WHERE token = ?1
+            "#,
+            token
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(user_id)
+    }
+}
+
+impl Contact {
+    /// A yearly all-day VEVENT on the contact's birthday, or `None` without a
+    /// valid birth date. February 29 falls on the last day of February in
+    /// other years.
+    pub fn to_ical_event(&self, stamp: &str) -> Option<String> {
+        let birth = NaiveDate::parse_from_str(&self.birth_date, "%Y-%m-%d").ok()?;
+        let start = match birth.year() {
+            NO_YEAR => birth.with_year(CALENDAR_NO_YEAR_START)?,
+            _ => birth,
+        };
+        let rule = match (birth.month(), birth.day()) {
+            (2, 29) => "RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1",
+            _ => "RRULE:FREQ=YEARLY",
+        };
+        let name = format!("{} {}", self.first_name.trim(), self.last_name.trim());
+        let lines = [
+            "BEGIN:VEVENT".to_owned(),
+            format!("UID:birthday-{}@contacts", self.id),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART;VALUE=DATE:{}", start.format("%Y%m%d")),
+            rule.to_owned(),
+            format!(
+                "SUMMARY:{}",
+                escape_vcard(&format!("{}'s birthday", name.trim()))
+            ),
+            "TRANSP:TRANSPARENT".to_owned(),
+            "END:VEVENT".to_owned(),
+        ];
+        Some(lines.iter().map(|line| fold_vcard_line(line)).collect())
+    }
+}
+
+/// An RFC 5545 calendar with a birthday event for each contact that has a
+/// birth date. TEXT values escape and fold like vCard ones.
+pub fn birthdays_calendar(contacts_set: &[Contact]) -> String {
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut calendar = [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//axum-3-htmx//Contacts birthdays//EN",
+        "CALSCALE:GREGORIAN",
+        "METHOD:PUBLISH",
+        "X-WR-CALNAME:Birthdays",
+    ]
+    .iter()
+    .map(|line| format!("{}\r\n", line))
+    .collect::<String>();
+    for contact in contacts_set {
+        if let Some(event) = contact.to_ical_event(&stamp) {
+            calendar.push_str(&event);
+        }
+    }
+    calendar.push_str("END:VCALENDAR\r\n");
+    calendar
+}
+
+// endregion: CALENDAR
+
+// region: QR CODE
+
+use qrcode::render::svg;
+use qrcode::{EcLevel, QrCode};
+
+/// Pixels per QR module in the PNG download.
+const QR_PNG_SCALE: usize = 8;
+/// Light modules around the code; scanners need at least four.
+const QR_QUIET_ZONE: usize = 4;
+
+/// What a shared contact's QR code encodes. MECARD is shorter, so it gives a
+/// less dense code, but fewer scanners understand it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    VCard,
+    Mecard,
+}
+
+/// Optional fields to put in a QR code; the name is always there.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrField {
+    Phone,
+    Email,
+    Birthday,
+    Address,
+    Organization,
+}
+
+/// Escapes a MECARD value: backslashes, semicolons, commas and colons.
+fn escape_mecard(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.trim().chars() {
+        if "\\;,:".contains(c) {
+            escaped.push('\\');
+        }
+        if c != '\r' && c != '\n' {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+impl Contact {
+    /// The text of this contact's QR code with only the chosen fields.
+    pub fn to_qr_payload(&self, format: QrFormat, fields: &[QrField]) -> String {
+        let shared = Contact {
+            phone: match fields.contains(&QrField::Phone) {
+                true => self.phone.clone(),
+                false => String::new(),
+            },
+            email: match fields.contains(&QrField::Email) {
+                true => self.email.clone(),
+                false => String::new(),
+            },
+            birth_date: match fields.contains(&QrField::Birthday) {
+                true => self.birth_date.clone(),
+                false => String::new(),
+            },
+            address: match fields.contains(&QrField::Address) {
+                true => self.address.clone(),
+                false => String::new(),
+            },
+            organization: match fields.contains(&QrField::Organization) {
+                true => self.organization.clone(),
+                false => String::new(),
+            },
+            ..self.clone()
+        };
+        match format {
+            QrFormat::VCard => shared.to_vcard(),
+            QrFormat::Mecard => shared.to_mecard(),
+        }
+    }
+    /// DoCoMo MECARD. BDAY needs a full date, so year-less birthdays are left
+    /// out.
+    fn to_mecard(&self) -> String {
+        let mut mecard = format!(
+            "MECARD:N:{},{};",
+            escape_mecard(&self.last_name),
+            escape_mecard(&self.first_name)
+        );
+        if !self.phone.trim().is_empty() {
+            mecard.push_str(&format!("TEL:{};", escape_mecard(&self.phone)));
+        }
+        if !self.email.trim().is_empty() {
+            mecard.push_str(&format!("EMAIL:{};", escape_mecard(&self.email)));
+        }
+        if !self.organization.trim().is_empty() {
+            mecard.push_str(&format!("ORG:{};", escape_mecard(&self.organization)));
+        }
+        if !self.address.trim().is_empty() {
+            let address = self.address.lines().map(str::trim).collect::<Vec<&str>>();
+            mecard.push_str(&format!("ADR:{};", escape_mecard(&address.join(", "))));
+        }
+        if let Ok(birth) = NaiveDate::parse_from_str(&self.birth_date, "%Y-%m-%d") {
+            if birth.year() != NO_YEAR {
+                mecard.push_str(&format!("BDAY:{};", birth.format("%Y%m%d")));
+            }
+        }
+        mecard.push(';');
+        mecard
+    }
+}
+
+fn qr_code(payload: &str) -> anyhow::Result<QrCode> {
+    Ok(QrCode::with_error_correction_level(payload, EcLevel::M)?)
+}
+
+/// The QR code as an SVG document without the XML declaration, so it can be
+/// served as a file or dropped into a page as is.
+pub fn qr_svg(payload: &str) -> anyhow::Result<String> {
+    let image = qr_code(payload)?
+        .render::<svg::Color>()
+        .min_dimensions(240, 240)
+        .quiet_zone(true)
+        .build();
+    Ok(match image.find("<svg") {
+        Some(start) => image[start..].to_owned(),
+        None => image,
+    })
+}
+
+/// The QR code as a black and white PNG.
+pub fn qr_png(payload: &str) -> anyhow::Result<Vec<u8>> {
+    let code = qr_code(payload)?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let side = (modules + 2 * QR_QUIET_ZONE) * QR_PNG_SCALE;
+    let mut pixels = vec![255u8; side * side];
+    for (index, color) in colors.iter().enumerate() {
+        if *color != qrcode::Color::Dark {
+            continue;
+        }
+        let left = (index % modules + QR_QUIET_ZONE) * QR_PNG_SCALE;
+        let top = (index / modules + QR_QUIET_ZONE) * QR_PNG_SCALE;
+        for y in top..top + QR_PNG_SCALE {
+            pixels[y * side + left..y * side + left + QR_PNG_SCALE].fill(0);
+        }
+    }
+    let mut png_bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png_bytes, side as u32, side as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(png_bytes)
+}
+
+// endregion: QR CODE
+
+// region: PDF
+
+use encoding_rs::WINDOWS_1252;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+
+/// US Letter, in points; Avery 5160 sheets only come in this size.
+const PDF_PAGE_WIDTH: f32 = 612.0;
+const PDF_PAGE_HEIGHT: f32 = 792.0;
+const PDF_MARGIN: f32 = 36.0;
+const PDF_FONT: Name = Name(b"F1");
+const PDF_FONT_BOLD: Name = Name(b"F2");
+/// WinAnsi code of the ellipsis that ends cut-off text, and its width.
+const PDF_ELLIPSIS: u8 = 0x85;
+const PDF_ELLIPSIS_WIDTH: f32 = 1000.0;
+
+/// Directory sheet columns: header, left edge and width.
+const DIRECTORY_COLUMNS: [(&str, f32, f32); 4] = [
+    ("Name", 0.0, 136.0),
+    ("Phone", 140.0, 96.0),
+    ("Email", 240.0, 161.0),
+    ("Address", 405.0, 135.0),
+];
+const DIRECTORY_FONT_SIZE: f32 = 9.0;
+const DIRECTORY_LEADING: f32 = 12.0;
+
+/// Avery 5160: 3 x 10 labels of 2 5/8" x 1" with 1/8" between columns.
+const LABEL_COLUMNS: usize = 3;
+const LABEL_ROWS: usize = 10;
+const LABEL_WIDTH: f32 = 189.0;
+const LABEL_HEIGHT: f32 = 72.0;
+const LABEL_PITCH: f32 = 198.0;
+const LABEL_LEFT: f32 = 13.5;
+const LABEL_TOP: f32 = 36.0;
+const LABEL_PADDING: f32 = 9.0;
+const LABEL_FONT_SIZE: f32 = 10.0;
+const LABEL_LEADING: f32 = 12.0;
+const LABEL_MAX_LINES: usize = 5;
+
+/// Helvetica advance widths of ASCII ' ' to '~', in 1/1000 em, from its AFM.
+/// Other characters are counted as a digit wide.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PdfLayout {
+    /// A phone list: name, phone, email and address in a table.
+    #[default]
+    Directory,
+    /// Avery 5160 mailing labels for the contacts with an address.
+    Labels,
+}
+
+impl PdfLayout {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            PdfLayout::Directory => "contacts-directory.pdf",
+            PdfLayout::Labels => "contacts-labels.pdf",
+        }
+    }
+}
+
+impl Contact {
+    /// Name, organization and address lines as they go on an envelope.
+    pub fn envelope_lines(&self) -> Vec<String> {
+        let name = format!("{} {}", self.first_name.trim(), self.last_name.trim());
+        [name.trim().to_owned(), self.organization.trim().to_owned()]
+            .into_iter()
+            .chain(self.address.lines().map(|line| line.trim().to_owned()))
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+}
+
+/// Text for the standard fonts, which only know WinAnsi; characters outside
+/// it are transliterated ("Łódź" -> "Lodz").
+fn win_ansi(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut buffer = [0; 4];
+    for c in text.chars().filter(|c| !c.is_control()) {
+        let (encoded, _, unmappable) = WINDOWS_1252.encode(c.encode_utf8(&mut buffer));
+        match unmappable {
+            false => bytes.extend_from_slice(&encoded),
+            true => bytes.extend(
+                deunicode_char(c)
+                    .unwrap_or("?")
+                    .bytes()
+                    .filter(|byte| (b' '..=b'~').contains(byte)),
+            ),
+        }
+    }
+    bytes
+}
+
+fn text_width(text: &[u8], size: f32) -> f32 {
+    let units = text
+        .iter()
+        .map(|byte| match byte {
+            b' '..=b'~' => HELVETICA_WIDTHS[(byte - b' ') as usize] as f32,
+            &PDF_ELLIPSIS => PDF_ELLIPSIS_WIDTH,
+            _ => 556.0,
+        })
+        .sum::<f32>();
+    units * size / 1000.0
+}
+
+/// The text in WinAnsi, cut with an ellipsis so it fits in `width`.
+fn fit_text(text: &str, size: f32, width: f32) -> Vec<u8> {
+    let mut bytes = win_ansi(text.trim());
+    if text_width(&bytes, size) <= width {
+        return bytes;
+    }
+    while !bytes.is_empty() && text_width(&bytes, size) + PDF_ELLIPSIS_WIDTH * size / 1000.0 > width
+    {
+        bytes.pop();
+    }
+    bytes.push(PDF_ELLIPSIS);
+    bytes
+}
+
+fn show_text(content: &mut Content, font: Name, size: f32, x: f32, y: f32, text: &[u8]) {
+    content.begin_text();
+    content.set_font(font, size);
+    content.next_line(x, y);
+    content.show(Str(text));
+    content.end_text();
+}
+
+/// The pages of the chosen layout; the directory always has at least one.
+pub fn contacts_pdf(contacts_set: &[Contact], layout: PdfLayout) -> Vec<u8> {
+    let (title, pages) = match layout {
+        PdfLayout::Directory => ("Contact directory", directory_pages(contacts_set)),
+        PdfLayout::Labels => ("Mailing labels", label_pages(contacts_set)),
+    };
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let font_id = Ref::new(3);
+    let font_bold_id = Ref::new(4);
+    let info_id = Ref::new(5);
+    let page_ids = (0..pages.len())
+        .map(|index| Ref::new(6 + 2 * index as i32))
+        .collect::<Vec<Ref>>();
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().copied())
+        .count(pages.len() as i32);
+    for (page_id, content) in page_ids.iter().zip(pages) {
+        let content_id = page_id.next();
+        let mut page = pdf.page(*page_id);
+        page.media_box(Rect::new(0.0, 0.0, PDF_PAGE_WIDTH, PDF_PAGE_HEIGHT));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        page.resources()
+            .fonts()
+            .pair(PDF_FONT, font_id)
+            .pair(PDF_FONT_BOLD, font_bold_id);
+        page.finish();
+        pdf.stream(content_id, &content.finish());
+    }
+    pdf.type1_font(font_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(font_bold_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.document_info(info_id)
+        .title(TextStr(title))
+        .producer(TextStr("axum-3-htmx"));
+    pdf.finish()
+}
+
+/// Rows are as tall as their address; a contact never breaks across pages.
+fn directory_pages(contacts_set: &[Contact]) -> Vec<Content> {
+    let table_top = PDF_PAGE_HEIGHT - PDF_MARGIN - 40.0;
+    let mut pages: Vec<Vec<&Contact>> = vec![Vec::new()];
+    let mut y = table_top;
+    for contact in contacts_set {
+        let height = DIRECTORY_LEADING * contact.address.lines().count().max(1) as f32 + 4.0;
+        if y - height < PDF_MARGIN + 12.0 && !pages.last().unwrap().is_empty() {
+            pages.push(Vec::new());
+            y = table_top;
+        }
+        pages.last_mut().unwrap().push(contact);
+        y -= height;
+    }
+    let page_count = pages.len();
+    let date = get_time();
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(index, page_contacts)| {
+            let mut content = Content::new();
+            show_text(
+                &mut content,
+                PDF_FONT_BOLD,
+                16.0,
+                PDF_MARGIN,
+                PDF_PAGE_HEIGHT - PDF_MARGIN - 16.0,
+                b"Contact directory",
+            );
+            let date_bytes = win_ansi(&date);
+            show_text(
+                &mut content,
+                PDF_FONT,
+                DIRECTORY_FONT_SIZE,
+                PDF_PAGE_WIDTH - PDF_MARGIN - text_width(&date_bytes, DIRECTORY_FONT_SIZE),
+                PDF_PAGE_HEIGHT - PDF_MARGIN - 16.0,
+                &date_bytes,
+            );
+            for (header, left, _) in DIRECTORY_COLUMNS {
+                show_text(
+                    &mut content,
+                    PDF_FONT_BOLD,
+                    DIRECTORY_FONT_SIZE,
+                    PDF_MARGIN + left,
+                    table_top + 6.0,
+                    header.as_bytes(),
+                );
+            }
+            content.set_line_width(0.5);
+            content.move_to(PDF_MARGIN, table_top + 2.0);
+            content.line_to(PDF_PAGE_WIDTH - PDF_MARGIN, table_top + 2.0);
+            content.stroke();
+            let mut y = table_top;
+            for (row, contact) in page_contacts.iter().enumerate() {
+                let address = contact.address.lines().collect::<Vec<&str>>();
+                let height = DIRECTORY_LEADING * address.len().max(1) as f32 + 4.0;
+                if row % 2 == 1 {
+                    content.set_fill_gray(0.93);
+                    content.rect(
+                        PDF_MARGIN,
+                        y - height,
+                        PDF_PAGE_WIDTH - 2.0 * PDF_MARGIN,
+                        height,
+                    );
+                    content.fill_nonzero();
+                    content.set_fill_gray(0.0);
+                }
+                let baseline = y - DIRECTORY_LEADING + 1.0;
+                let name = format!("{} {}", contact.first_name.trim(), contact.last_name.trim());
+                let cells = [
+                    name.as_str(),
+                    contact.phone.as_str(),
+                    contact.email.as_str(),
+                ];
+                for (text, (_, left, width)) in cells.iter().zip(DIRECTORY_COLUMNS) {
+                    let text = fit_text(text, DIRECTORY_FONT_SIZE, width);
+                    let x = PDF_MARGIN + left;
+                    show_text(
+                        &mut content,
+                        PDF_FONT,
+                        DIRECTORY_FONT_SIZE,
+                        x,
+                        baseline,
+                        &text,
+                    );
+                }
+                let (_, left, width) = DIRECTORY_COLUMNS[3];
+                for (line, text) in address.iter().enumerate() {
+                    let text = fit_text(text, DIRECTORY_FONT_SIZE, width);
+                    let line_y = baseline - DIRECTORY_LEADING * line as f32;
+                    let x = PDF_MARGIN + left;
+                    show_text(
+                        &mut content,
+                        PDF_FONT,
+                        DIRECTORY_FONT_SIZE,
+                        x,
+                        line_y,
+                        &text,
+                    );
+                }
+                y -= height;
+            }
+            let footer = format!("Page {} of {}", index + 1, page_count).into_bytes();
+            show_text(
+                &mut content,
+                PDF_FONT,
+                8.0,
+                (PDF_PAGE_WIDTH - text_width(&footer, 8.0)) / 2.0,
+                PDF_MARGIN / 2.0,
+                &footer,
+            );
+            content
+        })
+        .collect()
+}
+
+/// One label per contact with an address, filled across then down; lines
+/// past the fifth are left out and the block is centred on the label.
+fn label_pages(contacts_set: &[Contact]) -> Vec<Content> {
+    let labels = contacts_set
+        .iter()
+        .filter(|contact| !contact.address.trim().is_empty())
+        .map(Contact::envelope_lines)
+        .collect::<Vec<Vec<String>>>();
+    labels
+        .chunks(LABEL_COLUMNS * LABEL_ROWS)
+        .map(|sheet| {
+            let mut content = Content::new();
+            for (index, lines) in sheet.iter().enumerate() {
+                let left = LABEL_LEFT + LABEL_PITCH * (index % LABEL_COLUMNS) as f32;
+                let top =
+                    PDF_PAGE_HEIGHT - LABEL_TOP - LABEL_HEIGHT * (index / LABEL_COLUMNS) as f32;
+                let shown = lines.len().min(LABEL_MAX_LINES);
+                let block = LABEL_LEADING * shown as f32;
+                let first_baseline = top - (LABEL_HEIGHT - block) / 2.0 - LABEL_FONT_SIZE + 1.0;
+                for (line, text) in lines.iter().take(shown).enumerate() {
+                    let text = fit_text(text, LABEL_FONT_SIZE, LABEL_WIDTH - 2.0 * LABEL_PADDING);
+                    show_text(
+                        &mut content,
+                        PDF_FONT,
+                        LABEL_FONT_SIZE,
+                        left + LABEL_PADDING,
+                        first_baseline - LABEL_LEADING * line as f32,
+                        &text,
+                    );
+                }
+            }
+            content
+        })
+        .collect()
+}
+
+// endregion: PDF
+
+// region: EXPORT TEMPLATES
+
+use minijinja::{AutoEscape, Environment};
+
+/// Template steps one render may take: plenty for thousands of contacts, but
+/// a runaway loop stops instead of hanging the server.
+const TEMPLATE_FUEL: u64 = 5_000_000;
+/// Contacts the editor preview renders.
+pub const TEMPLATE_PREVIEW_CONTACTS: usize = 5;
+/// Output types a template can produce: file extension and content type.
+pub const TEMPLATE_EXTENSIONS: [(&str, &str); 4] = [
+    ("txt", "text/plain; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("html", "text/html; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+];
+/// What the editor starts with: a Markdown phone list.
+pub const DEFAULT_EXPORT_TEMPLATE: &str = "| Name | Phone | Email |
+|------|-------|-------|
+{% for contact in contacts -%}
+| {{ contact.first_name }} {{ contact.last_name }} | {{ contact.phone }} | {{ contact.email }} |
+{% endfor %}
+{{ count }} contacts, {{ today }}
+";
+
+/// A user's own export format, rendered with MiniJinja. Templates only see
+/// the contacts handed to them: there is no loader, so no file access.
+#[derive(Debug, Default, Clone, FromRow)]
+pub struct ExportTemplate {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub extension: String,
+    pub body: String,
+    pub time_creation: String,
+}
+
+impl ExportTemplate {
+    pub async fn list(pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<Vec<ExportTemplate>> {
+        let templates_set = sqlx::query_as!(
+            ExportTemplate,
+            r#"
+            SELECT *
+            FROM export_templates_table
+            WHERE user_id = ?1
+            ORDER BY name COLLATE NOCASE
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(templates_set)
+    }
+    pub async fn get(
+        pool: &Pool<Sqlite>,
+        id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<Option<ExportTemplate>> {
+        let template_set = sqlx::query_as!(
+            ExportTemplate,
+            r#"
+            SELECT *
+            FROM export_templates_table
+            WHERE id = ?1 AND user_id = ?2
+            "#,
+            id,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(template_set)
+    }
+    /// Inserts a new template (id 0) or updates the user's own; returns its id.
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> anyhow::Result<i64> {
+        if self.id == 0 {
+            let timestamp_str = get_time();
+            let id_inserted = sqlx::query!(
+                r#"
+                INSERT INTO export_templates_table ( user_id, name, extension, body, time_creation)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                "#,
+                self.user_id,
+                self.name,
+                self.extension,
+                self.body,
+                timestamp_str
+            )
+            .execute(pool)
+            .await?
+            .last_insert_rowid();
+            return Ok(id_inserted);
+        }
+        sqlx::query!(
+            r#"
+            UPDATE export_templates_table
+            SET name = ?1,
+                extension = ?2,
+                body = ?3
+            WHERE id = ?4 AND user_id = ?5
+            "#,
+            self.name,
+            self.extension,
+            self.body,
+            self.id,
+            self.user_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(self.id)
+    }
+    pub async fn delete(pool: &Pool<Sqlite>, id: i64, user_id: i64) -> anyhow::Result<u64> {
+        let rows_affected = sqlx::query!(
+            r#"
+            DELETE FROM export_templates_table
+            WHERE id = ?1 AND user_id = ?2
+            "#,
+            id,
+            user_id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+        Ok(rows_affected)
+    }
+    pub fn content_type(&self) -> &'static str {
+        TEMPLATE_EXTENSIONS
+            .iter()
+            .find(|(extension, _)| *extension == self.extension)
+            .map(|(_, content_type)| *content_type)
+            .unwrap_or("text/plain; charset=utf-8")
+    }
+    /// e.g. `front-desk-list.md`.
+    pub fn file_name(&self) -> String {
+        let name = fold_words(&self.name)
+            .into_iter()
+            .map(|(_, folded)| folded)
+            .collect::<Vec<String>>()
+            .join("-");
+        match name.is_empty() {
+            true => format!("contacts.{}", self.extension),
+            false => format!("{}.{}", name, self.extension),
+        }
+    }
+}
+
+fn template_environment(extension: &str) -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_fuel(Some(TEMPLATE_FUEL));
+    // HTML output escapes contact fields, like the pages of this app do.
+    let auto_escape = match extension {
+        "html" => AutoEscape::Html,
+        _ => AutoEscape::None,
+    };
+    env.set_auto_escape_callback(move |_| auto_escape);
+    env
+}
+
+/// Checks the template syntax without rendering it.
+pub fn check_export_template(body: &str, extension: &str) -> Result<(), minijinja::Error> {
+    template_environment(extension).template_from_str(body)?;
+    Ok(())
+}
+
+/// Renders a template with `contacts` (each with the `Contact` fields),
+/// `count` and `today` in its context.
+pub fn render_export_template(
+    body: &str,
+    extension: &str,
+    contacts_set: &[Contact],
+) -> Result<String, minijinja::Error> {
+    let env = template_environment(extension);
+    let template = env.template_from_str(body)?;
+    let contacts_set = contacts_set
+        .iter()
+        .map(|contact| Contact {
+            birth_date: contact.birth_date_display(),
+            ..contact.clone()
+        })
+        .collect::<Vec<Contact>>();
+    template.render(minijinja::context! {
+        contacts => contacts_set,
+        count => contacts_set.len(),
+        today => get_time(),
+    })
+}
+
+// endregion: EXPORT TEMPLATES
+
+// region: USERS
+
+use axum::async_trait;
+use axum_login::{AuthUser, AuthnBackend, UserId};
+use password_auth::verify_password;
+use sqlx::SqlitePool;
+use tokio::task;
+
+use crate::routers::CredentialsParam;
 
 #[derive(Clone, Deserialize, FromRow)]
 pub struct User {
     id: i64,
     pub username: String,
     password: String,
+    pub index_by: String,
+    pub display_name: String,
+    pub email: String,
+    /// IANA name, one of `timezones()`.
+    pub timezone: String,
 }
 // Here we've implemented `Debug` manually to avoid accidentally logging the
 // password hash.
@@ -473,6 +5760,10 @@ impl std::fmt::Debug for User {
             .field("id", &self.id)
             .field("username", &self.username)
             .field("password", &"[redacted]")
+            .field("index_by", &self.index_by)
+            .field("display_name", &self.display_name)
+            .field("email", &self.email)
+            .field("timezone", &self.timezone)
             .finish()
     }
 }
@@ -525,7 +5816,7 @@ impl AuthnBackend for Backend {
             User,
             r#"
             SELECT * FROM users_table 
-            WHERE username = ?
+            WHERE username = ? COLLATE NOCASE
             "#,
             creds.username
         )
@@ -553,6 +5844,107 @@ impl AuthnBackend for Backend {
     }
 }
 
+impl User {
+    pub fn index_by(&self) -> IndexBy {
+        IndexBy::from_sort(&self.index_by).unwrap_or(IndexBy::LastName)
+    }
+    pub fn profile(&self) -> UserProfile {
+        UserProfile {
+            display_name: self.display_name.clone(),
+            email: self.email.clone(),
+            timezone: self.timezone.clone(),
+        }
+    }
+}
+
+/// What the account page lets users say about themselves.
+#[derive(Debug, Clone, Default)]
+pub struct UserProfile {
+    pub display_name: String,
+    pub email: String,
+    pub timezone: String,
+}
+
+impl UserProfile {
+    /// Everything wrong with the profile, empty when it can be saved.
+    pub fn check(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.display_name.chars().count() > 100 {
+            errors.push("Display name must be at most 100 characters".to_string());
+        }
+        let email_valid = match self.email.split_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty()
+                    && domain.contains('.')
+                    && !domain.contains('@')
+                    && !self.email.contains(char::is_whitespace)
+            }
+            None => false,
+        };
+        if !self.email.is_empty() && !email_valid {
+            errors.push(format!("\"{}\" is not an email address", self.email));
+        }
+        if !is_timezone(&self.timezone) {
+            errors.push(format!("Unknown time zone \"{}\"", self.timezone));
+        }
+        errors
+    }
+    pub async fn save(&self, pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<u32> {
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE users_table
+            SET display_name = ?1, email = ?2, timezone = ?3
+            WHERE id = ?4
+            "#,
+            self.display_name,
+            self.email,
+            self.timezone,
+            user_id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+        Ok(rows_affected as u32)
+    }
+}
+
+/// The time zones offered on the account page, from `timezones.txt`.
+pub fn timezones() -> &'static [&'static str] {
+    static TIMEZONES: OnceLock<Vec<&'static str>> = OnceLock::new();
+    TIMEZONES.get_or_init(|| {
+        include_str!("timezones.txt")
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    })
+}
+
+pub fn is_timezone(name: &str) -> bool {
+    timezones().contains(&name)
+}
+
+pub async fn update_index_by(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    index_by: IndexBy,
+) -> anyhow::Result<u32> {
+    let index_str = index_by.sort();
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE users_table
+        SET index_by = ?1
+        WHERE id = ?2
+        "#,
+        index_str,
+        user_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected as u32)
+}
+
 // We use a type alias for convenience.
 //
 // Note that we've supplied our concrete backend here.
@@ -564,6 +5956,72 @@ pub struct UserErrorState {
     pub password: String,
 }
 
+/// Rules a new password has to follow, set with `PASSWORD_MIN_LENGTH`,
+/// `PASSWORD_CHECK_COMMON` and `PASSWORD_CHECK_USERNAME`.
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// Refuse the passwords in `common-passwords.txt`.
+    pub check_common: bool,
+    /// Refuse passwords containing the username.
+    pub check_username: bool,
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> anyhow::Result<PasswordPolicy> {
+        fn setting<T: std::str::FromStr>(name: &str, default: T) -> anyhow::Result<T> {
+            match std::env::var(name) {
+                Ok(value) => value
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("{} is not valid: {:?}", name, value)),
+                Err(_) => Ok(default),
+            }
+        }
+        Ok(PasswordPolicy {
+            min_length: setting("PASSWORD_MIN_LENGTH", 10)?,
+            check_common: setting("PASSWORD_CHECK_COMMON", true)?,
+            check_username: setting("PASSWORD_CHECK_USERNAME", true)?,
+        })
+    }
+    /// What is wrong with the password, or an empty string when it may be used.
+    pub fn check(&self, username: &str, password: &str) -> String {
+        let lower = password.to_lowercase();
+        // "Summer2024!" is as easy to guess as "summer".
+        let stem = lower.trim_end_matches(|c: char| !c.is_alphabetic());
+        let username = username.trim().to_lowercase();
+        if password.is_empty() {
+            "Password Required".to_string()
+        } else if password.chars().count() < self.min_length {
+            format!(
+                "Password must be at least {} characters long",
+                self.min_length
+            )
+        } else if self.check_common
+            && (is_common_password(&lower) || (stem.len() > 3 && is_common_password(stem)))
+        {
+            "Password is too common, choose one that is harder to guess".to_string()
+        } else if self.check_username && username.chars().count() > 2 && lower.contains(&username) {
+            "Password must not contain the username".to_string()
+        } else {
+            "".to_string()
+        }
+    }
+}
+
+fn is_common_password(password: &str) -> bool {
+    static COMMON: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    COMMON
+        .get_or_init(|| {
+            include_str!("common-passwords.txt")
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .collect()
+        })
+        .contains(password)
+}
+
 pub async fn check_user_errors(
     username: &String,
     password: &str,
@@ -576,7 +6034,7 @@ pub async fn check_user_errors(
             let result = sqlx::query!(
                 r#"
                     SELECT COUNT(*) as count FROM users_table
-                    WHERE username = ?1
+                    WHERE username = ?1 COLLATE NOCASE
                     "#,
                 username
             )
@@ -585,14 +6043,10 @@ pub async fn check_user_errors(
             let email_equal = result.count;
             match email_equal {
                 0 => "".to_string(),
-                _ => "Usename must be unique".to_string(),
+                _ => format!("Username \"{}\" is already taken", username),
             }
         },
-        password: if password.is_empty() {
-            "Password Required".to_string()
-        } else {
-            "".to_string()
-        },
+        password: PasswordPolicy::from_env()?.check(username, password),
     };
 
     if new_error.username.is_empty() && new_error.password.is_empty() {
@@ -602,19 +6056,55 @@ pub async fn check_user_errors(
     }
 }
 
+/// Usernames that only differ in case from another account's, as
+/// `id: username`. The case-insensitive username migration refuses to run
+/// while there are any, so they are listed before migrating.
+pub async fn username_case_clashes(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<String>> {
+    // Plain queries: a new database has no users table yet.
+    let has_users: bool = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) > 0
+        FROM sqlite_master
+        WHERE type = 'table' AND name = 'users_table'
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+    if !has_users {
+        return Ok(Vec::new());
+    }
+    let clashes: Vec<(i64, String)> = sqlx::query_as(
+        r#"
+        SELECT id, username
+        FROM users_table
+        WHERE username COLLATE NOCASE IN (
+            SELECT username FROM users_table
+            GROUP BY username COLLATE NOCASE
+            HAVING COUNT(*) > 1
+        )
+        ORDER BY username COLLATE NOCASE, id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(clashes
+        .into_iter()
+        .map(|(id, username)| format!("{}: {}", id, username))
+        .collect())
+}
+
+/// Creates the account. An account created while it is the oldest one adopts
+/// the contacts without an owner, i.e. those added before accounts owned
+/// contacts.
 pub async fn create_user(
     username: String,
     password: String,
     pool: Pool<Sqlite>,
 ) -> anyhow::Result<u32> {
     //let timestamp_str = get_time();
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let password_hash = argon2
-        .hash_password(password.as_bytes(), &salt)?
-        .to_string();
+    let password_hash = hash_password(&password)?;
 
-    let mut conn = pool.acquire().await?;
+    let mut tx = pool.begin().await?;
     let id_inserted = sqlx::query!(
         r#"
         INSERT INTO users_table ( username, password)
@@ -624,10 +6114,132 @@ pub async fn create_user(
         password_hash,
         //timestamp_str
     )
-    .execute(&mut *conn)
+    .execute(&mut *tx)
     .await?
     .last_insert_rowid();
+    sqlx::query!(
+        r#"
+        UPDATE contacts_table
+        SET user_id = ?1
+        WHERE user_id IS NULL
+        AND ?1 = (SELECT MIN(id) FROM users_table)
+        "#,
+        id_inserted
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
     Ok(id_inserted as u32)
 }
 
+fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let argon2 = Argon2::default();
+    Ok(argon2
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Replaces the password hash. Since the hash is the session auth hash, every
+/// session logged in with the old password stops working.
+pub async fn update_password(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    password: &str,
+) -> anyhow::Result<u32> {
+    let password_hash = hash_password(password)?;
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE users_table
+        SET password = ?1
+        WHERE id = ?2
+        "#,
+        password_hash,
+        user_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected as u32)
+}
+
+/// How long the copy of a deleted account's archive is kept in case its
+/// download failed, in seconds.
+pub const DELETED_ARCHIVE_SECONDS: u64 = 3600;
+
+/// Where deleted accounts' archives wait out `DELETED_ARCHIVE_SECONDS`, apart
+/// from the archiver's files.
+pub fn deleted_archive_dir() -> PathBuf {
+    archive_dir().join("deleted")
+}
+
+/// Every minute, removes the abandoned uploads and the deleted accounts'
+/// archives that have expired.
+pub async fn run_expiry_sweeper() {
+    loop {
+        for (dir, seconds) in [
+            (upload_dir(), UPLOAD_SECONDS),
+            (deleted_archive_dir(), DELETED_ARCHIVE_SECONDS),
+        ] {
+            if let Err(error) = remove_expired(&dir, seconds).await {
+                println!("->> {} - EXPIRY FAILED: {}", get_time(), error);
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    }
+}
+
+/// Removes the user, with their contacts, saved searches, templates, calendar
+/// feed and archive files. What was removed is first written to
+/// `deleted_archive_dir()` as a contacts archive, kept for
+/// `DELETED_ARCHIVE_SECONDS` so nothing is lost if the download fails;
+/// returns its file name and content.
+pub async fn delete_user(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    username: &str,
+) -> anyhow::Result<(String, Vec<u8>)> {
+    let jobs = ArchiveJob::list(pool, user_id).await?;
+    let saved_searches = SavedSearch::list_searches(pool, user_id).await?;
+    let schema_version = schema_version(pool).await?;
+    let mut tx = pool.begin().await?;
+    let archive = ContactsArchive {
+        format: ARCHIVE_FORMAT.to_owned(),
+        schema_version,
+        created: Local::now().to_rfc3339(),
+        username: username.to_owned(),
+        contacts: Contacts::owned_contacts(&mut tx, user_id, -1, 0).await?,
+        saved_searches,
+    };
+    let content = serde_json::to_vec_pretty(&archive)?;
+    let file_name = archive_file_name(user_id, None, false);
+    tokio::fs::create_dir_all(deleted_archive_dir()).await?;
+    tokio::fs::write(deleted_archive_dir().join(&file_name), &content).await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM contacts_table
+        WHERE user_id = ?1
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    // Saved searches, archive jobs, templates and the calendar token cascade.
+    sqlx::query!(
+        r#"
+        DELETE FROM users_table
+        WHERE id = ?1
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    for job in jobs {
+        let _ = tokio::fs::remove_file(job.path()).await;
+        let _ = tokio::fs::remove_file(job.partial_path()).await;
+    }
+    Ok((file_name, content))
+}
+
 // endregion: USERS
@@ -1,14 +1,23 @@
 use crate::models::*;
 use askama::Template;
-use axum::extract::{Query, State};
-use axum::http::{header, HeaderMap, StatusCode};
-use axum::response::{IntoResponse, Redirect};
+use axum::extract::{DefaultBodyLimit, Multipart, Query, Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Redirect, Response};
 use axum::routing::{get, post};
-use axum::{Form, Router};
+use axum::{Form, Json, Router};
 use axum_extra::extract::Form as ExtraForm;
+use axum_extra::extract::Query as ExtraQuery;
+use axum_login::{AuthUser, AuthnBackend};
 use axum_messages::Level;
 use axum_messages::{Message, Messages};
+use futures_util::stream;
 use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use tokio::io::AsyncWriteExt;
 use tokio_util::io::ReaderStream;
 
 // region: INDEX_REG
@@ -16,7 +25,6 @@ use tokio_util::io::ReaderStream;
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct RootTemplate {
-    pub archive_t: ArchiverState,
     pub username: Option<String>,
 }
 
@@ -27,10 +35,7 @@ pub fn index_router() -> Router<AppState> {
 mod get {
     use super::*;
 
-    pub async fn handler_root(
-        State(state): State<ArchiverStateType>,
-        auth_session: AuthSession,
-    ) -> Result<impl IntoResponse, AppError> {
+    pub async fn handler_root(auth_session: AuthSession) -> Result<impl IntoResponse, AppError> {
         println!("->> {} - HANDLER: handler_root", get_time());
         let username: Option<String>;
         if let Some(user) = auth_session.user {
@@ -40,7 +45,6 @@ mod get {
         };
         let root_tmpl = RootTemplate {
             username,
-            archive_t: state.read().await.clone(),
             //user: Option<User>,
         };
         Ok(root_tmpl.into_response())
@@ -60,30 +64,49 @@ pub struct ShowTemplate<'a> {
     pub length_t: u32,
     pub page_t: u32,
     pub max_page_t: u32,
-    pub archive_t: ArchiverState,
     pub time_t: String,
     pub birthday_t: u32,
+    pub sort_t: &'a str,
+    pub window_t: u32,
+    pub suggestion_t: Option<String>,
+    pub letters_t: Vec<(char, Option<u32>)>,
+    pub index_t: &'a str,
     //pub user: Option<User>,
     pub username: Option<String>,
 }
 
+impl ShowTemplate<'_> {
+    /// Export columns with whether they are ticked by default.
+    pub fn export_columns(&self) -> Vec<(&'static str, bool)> {
+        EXPORT_COLUMNS
+            .iter()
+            .map(|column| (*column, !matches!(*column, "id" | "time_creation")))
+            .collect()
+    }
+}
+
 #[derive(Template)]
 //#[template(path = "show.html", block = "rows")]
 #[template(path = "show_rows.html")]
-
-pub struct RowsTemplate {
+pub struct RowsTemplate<'a> {
     pub contacts_t: Vec<Contact>,
+    pub search_t: &'a str,
     pub length_t: u32,
     pub page_t: u32,
     pub max_page_t: u32,
     pub birthday_t: u32,
+    pub sort_t: &'a str,
+    pub window_t: u32,
+    pub suggestion_t: Option<String>,
+    pub index_t: &'a str,
 }
 
 #[derive(Deserialize)]
-pub struct ShowParams {
+pub struct IndexByParams {
+    pub index_p: String,
     pub search_p: Option<String>,
-    pub page_p: u32,
     pub birthday_p: u32,
+    pub window_p: Option<u32>,
 }
 
 #[derive(Deserialize)]
@@ -93,10 +116,12 @@ pub struct DeleteBulkParams {
 }
 
 pub fn show_router() -> Router<AppState> {
-    Router::new().route(
-        "/contacts/show",
-        get(handler_get_showcontacts).delete(handler_delete_bulk),
-    )
+    Router::new()
+        .route(
+            "/contacts/show",
+            get(handler_get_showcontacts).delete(handler_delete_bulk),
+        )
+        .route("/contacts/index_by", post(handler_post_index_by))
 }
 
 pub async fn handler_get_showcontacts(
@@ -108,21 +133,24 @@ pub async fn handler_get_showcontacts(
     //auth_session: AuthSession,
 ) -> Result<impl IntoResponse, AppError> {
     println!("->> {} - HANDLER: handler_get_showcontacts", get_time());
-    //std::thread::sleep(std::time::Duration::from_millis(900));
-    let username: Option<String>;
-    if let Some(user) = auth_session.user {
-        username = Some(user.username);
-    } else {
-        username = None;
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login").into_response());
     };
+    let index_by = params
+        .index_p
+        .as_deref()
+        .and_then(IndexBy::from_sort)
+        .unwrap_or(user.index_by());
+    let username = Some(user.username.clone());
 
     //if let Some(user) = auth_session.user {
     let search_bar = params.search_p.as_deref().unwrap_or("");
     let page_set = params.page_p;
     let birthday_set = params.birthday_p;
+    let sort_set = params.sort_p.as_deref().unwrap_or("");
+    let window_set = birthday_window(params.window_p);
 
     let pool = state.pool_state.read().await.clone();
-    let archiver = state.archiver_state.read().await.clone();
     let mut writable_state = state.contact_error_state.write().await;
     *writable_state = CreationErrorState::default();
 
@@ -132,28 +160,50 @@ pub async fn handler_get_showcontacts(
     .collect::<Vec<_>>()
     .join(", "); */
 
-    let (contacts_set, length, page_set, max_page) =
-        Contacts::match_contacts(pool, search_bar, page_set, birthday_set).await?;
+    let view = Contacts::show_contacts(
+        &pool,
+        user.id(),
+        search_bar,
+        birthday_set,
+        sort_set,
+        window_set,
+        index_by,
+        page_set,
+    )
+    .await?;
+    let letters = view.letters;
+    let suggestion = view.suggestion;
+    let (contacts_set, page_set, max_page) = (view.contacts, view.page, view.max_page);
+    let length = contacts_set.len() as u32;
 
     let time_now = get_time();
 
     let rows_tmpl = RowsTemplate {
-        contacts_t: contacts_set.contacts.clone(),
+        contacts_t: contacts_set.clone(),
+        search_t: search_bar,
         length_t: length,
         page_t: page_set,
         max_page_t: max_page,
         birthday_t: birthday_set,
+        sort_t: sort_set,
+        window_t: window_set,
+        suggestion_t: suggestion.clone(),
+        index_t: index_by.sort(),
     };
     let contacts_tmpl = ShowTemplate {
         messages_t: messages.into_iter().collect(),
         search_t: search_bar,
-        contacts_t: contacts_set.contacts,
+        contacts_t: contacts_set,
         length_t: length,
         page_t: page_set,
         max_page_t: max_page,
-        archive_t: archiver,
         time_t: time_now,
         birthday_t: birthday_set,
+        sort_t: sort_set,
+        window_t: window_set,
+        suggestion_t: suggestion,
+        letters_t: letters,
+        index_t: index_by.sort(),
         //user: auth_session.user,
         username,
     };
@@ -162,18 +212,19 @@ pub async fn handler_get_showcontacts(
     match header_hx {
         Some(header_value) => match header_value.to_str()? {
             "search" => {
-                std::thread::sleep(std::time::Duration::from_millis(900));
-                Ok(([(header::VARY, "HX-Trigger")], rows_tmpl.into_response()))
+                Ok(([(header::VARY, "HX-Trigger")], rows_tmpl.into_response()).into_response())
             }
             _ => Ok((
                 [(header::VARY, "HX-Trigger")],
                 contacts_tmpl.into_response(),
-            )),
+            )
+                .into_response()),
         },
         None => Ok((
             [(header::VARY, "HX-Trigger")],
             contacts_tmpl.into_response(),
-        )),
+        )
+            .into_response()),
     }
     /*} else {
         Ok((
@@ -183,12 +234,102 @@ pub async fn handler_get_showcontacts(
     } */
 }
 
+#[derive(Template)]
+#[template(path = "suggest.html")]
+pub struct SuggestTemplate {
+    pub suggestions_t: Vec<ContactSuggestion>,
+}
+
+#[derive(Deserialize)]
+pub struct SuggestParams {
+    #[serde(alias = "search_p")]
+    pub q: Option<String>,
+    pub limit_p: Option<u32>,
+}
+
+pub fn suggest_router() -> Router<AppState> {
+    Router::new().route("/contacts/suggest", get(handler_get_suggest))
+}
+
+/// Autocomplete for the search bar and contact pickers. Answers JSON when the
+/// client asks for it, an htmx dropdown fragment otherwise.
+pub async fn handler_get_suggest(
+    State(pool_state): State<PoolStateType>,
+    auth_session: AuthSession,
+    Query(params): Query<SuggestParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_suggest", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let query = params.q.as_deref().unwrap_or("").trim();
+    let limit = params.limit_p.unwrap_or(8).clamp(1, 20);
+    let pool = pool_state.read().await.clone();
+    let suggestions_set = match query {
+        "" => Vec::new(),
+        _ => Contacts::suggest_contacts(&pool, user.id(), query, limit)
+            .await?
+            .iter()
+            .map(ContactSuggestion::from)
+            .collect(),
+    };
+
+    let wants_json = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+    match wants_json {
+        true => Ok(([(header::VARY, "Accept")], Json(suggestions_set)).into_response()),
+        false => Ok((
+            [(header::VARY, "Accept")],
+            SuggestTemplate {
+                suggestions_t: suggestions_set,
+            },
+        )
+            .into_response()),
+    }
+}
+
+/// Switches the A-Z index between first and last names, remembering the choice
+/// for logged in users, and reopens the list ordered that way.
+pub async fn handler_post_index_by(
+    State(pool_state): State<PoolStateType>,
+    auth_session: AuthSession,
+    Form(params): Form<IndexByParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_index_by", get_time());
+    let index_by = IndexBy::from_sort(&params.index_p).unwrap_or(IndexBy::LastName);
+    if let Some(user) = auth_session.user {
+        let pool = pool_state.read().await.clone();
+        update_index_by(&pool, user.id(), index_by).await?;
+    }
+    let show_params = ShowParams {
+        search_p: params.search_p.filter(|search| !search.is_empty()),
+        page_p: 1,
+        birthday_p: params.birthday_p,
+        sort_p: Some(index_by.sort().to_owned()),
+        window_p: params.window_p,
+        index_p: Some(index_by.sort().to_owned()),
+    };
+    let uri = format!(
+        "/contacts/show?{}",
+        serde_urlencoded::to_string(&show_params)?
+    );
+    Ok(Redirect::to(uri.as_str()))
+}
+
 pub async fn handler_delete_bulk(
     messages: Messages,
     State(pool_state): State<PoolStateType>,
+    auth_session: AuthSession,
     ExtraForm(params_form): ExtraForm<DeleteBulkParams>,
 ) -> anyhow::Result<impl IntoResponse, AppError> {
     println!("->> {} - HANDLER: handler_delete_bulk", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let user_id = user.id();
     let ids_opt: Option<Vec<String>> = params_form.ids_p;
     let pool = pool_state.read().await.clone();
     let mut rows_affected_sum: u32 = 0;
@@ -202,9 +343,10 @@ pub async fn handler_delete_bulk(
                 let rows_affected = sqlx::query!(
                     r#"
                         DELETE FROM contacts_table
-                        WHERE id = ?1
+                        WHERE id = ?1 AND user_id = ?2
                         "#,
-                    id_set
+                    id_set,
+                    user_id
                 )
                 .execute(&pool)
                 .await?
@@ -232,7 +374,6 @@ pub async fn handler_delete_bulk(
 #[template(path = "view.html")]
 pub struct ViewContactTemplate {
     pub contact_t: Contact,
-    pub archive_t: ArchiverState,
     pub username: Option<String>,
 }
 
@@ -241,11 +382,24 @@ pub struct ViewContactParams {
     pub id_p: u32,
 }
 
+#[derive(Deserialize)]
+pub struct QrParams {
+    pub id_p: u32,
+    #[serde(default)]
+    pub format_p: QrFormat,
+    #[serde(default)]
+    pub fields_p: Vec<QrField>,
+}
+
 pub fn view_router() -> Router<AppState> {
-    Router::new().route(
-        "/contacts/view",
-        get(handler_get_viewcontact).delete(handler_delete_contact),
-    )
+    Router::new()
+        .route(
+            "/contacts/view",
+            get(handler_get_viewcontact).delete(handler_delete_contact),
+        )
+        .route("/contacts/view/vcard", get(handler_get_vcard))
+        .route("/contacts/view/qr", get(handler_get_qr))
+        .route("/contacts/view/qr.png", get(handler_get_qr_png))
 }
 
 pub async fn handler_get_viewcontact(
@@ -254,30 +408,19 @@ pub async fn handler_get_viewcontact(
     auth_session: AuthSession,
 ) -> Result<impl IntoResponse, AppError> {
     println!("->> {} - HANDLER: handler_get_viewcontact", get_time());
-    let username: Option<String>;
-    if let Some(user) = auth_session.user {
-        username = Some(user.username);
-    } else {
-        username = None;
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login").into_response());
     };
+    let username = Some(user.username.clone());
 
     let id_set = params.id_p;
 
     let pool = state.pool_state.read().await.clone();
-    let contact_set = sqlx::query_as!(
-        Contact,
-        r#"
-            SELECT *
-            FROM contacts_table
-            WHERE id = ?
-            "#,
-        id_set
-    )
-    .fetch_one(&pool)
-    .await?;
+    let Some(contact_set) = Contact::get_contact(&pool, id_set, user.id()).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
     let view_contact_template = ViewContactTemplate {
         contact_t: contact_set,
-        archive_t: state.archiver_state.read().await.clone(),
         username,
     };
     Ok(view_contact_template.into_response())
@@ -285,11 +428,16 @@ pub async fn handler_get_viewcontact(
 
 pub async fn handler_delete_contact(
     State(state): State<AppState>,
+    auth_session: AuthSession,
     Query(params_query): Query<ViewContactParams>,
     headers: HeaderMap,
     messages: Messages,
 ) -> Result<impl IntoResponse, AppError> {
     println!("->> {} - HANDLER: handler_delete_contact", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let user_id = user.id();
     //let id_set = params_query.id_p as i64;
     let id_set = params_query.id_p;
     let header_hx_trigger = headers.get("HX-trigger");
@@ -298,9 +446,10 @@ pub async fn handler_delete_contact(
     let rows_affected = sqlx::query!(
         r#"
             DELETE FROM contacts_table
-            WHERE id = ?1
+            WHERE id = ?1 AND user_id = ?2
             "#,
-        id_set
+        id_set,
+        user_id
     )
     .execute(&pool)
     .await?
@@ -322,27 +471,417 @@ pub async fn handler_delete_contact(
     }
 }
 
+pub async fn handler_get_vcard(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    Query(params): Query<ViewContactParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_vcard", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    match Contact::get_contact(&pool, params.id_p, user.id()).await? {
+        Some(contact_set) => {
+            Ok(vcard_response(&vcard_file_name(&contact_set), &[contact_set]).into_response())
+        }
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+/// The contact's QR code as SVG; view.html swaps it in whenever the chosen
+/// format or fields change.
+pub async fn handler_get_qr(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    ExtraQuery(params): ExtraQuery<QrParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_qr", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    match Contact::get_contact(&pool, params.id_p, user.id()).await? {
+        Some(contact_set) => {
+            let payload = contact_set.to_qr_payload(params.format_p, &params.fields_p);
+            let headers = [(header::CONTENT_TYPE, "image/svg+xml")];
+            Ok((headers, qr_svg(&payload)?).into_response())
+        }
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+pub async fn handler_get_qr_png(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    ExtraQuery(params): ExtraQuery<QrParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_qr_png", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    match Contact::get_contact(&pool, params.id_p, user.id()).await? {
+        Some(contact_set) => {
+            let payload = contact_set.to_qr_payload(params.format_p, &params.fields_p);
+            let file_name = vcard_file_name(&contact_set).replace(".vcf", "-qr.png");
+            let headers = [
+                (header::CONTENT_TYPE, "image/png".to_owned()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", file_name),
+                ),
+            ];
+            Ok((headers, qr_png(&payload)?).into_response())
+        }
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
 // endregion: VIEW
 
+// region: EXPORT
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    #[serde(default, alias = "format")]
+    pub format_p: ExportFormat,
+    pub search_p: Option<String>,
+    #[serde(default)]
+    pub birthday_p: u32,
+    pub sort_p: Option<String>,
+    pub window_p: Option<u32>,
+    #[serde(default)]
+    pub columns_p: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PdfParams {
+    #[serde(default)]
+    pub layout_p: PdfLayout,
+    pub search_p: Option<String>,
+    #[serde(default)]
+    pub birthday_p: u32,
+    pub sort_p: Option<String>,
+    pub window_p: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct PdfSelectionParams {
+    #[serde(default)]
+    pub layout_p: PdfLayout,
+    pub ids_p: Option<Vec<String>>,
+}
+
+pub fn export_router() -> Router<AppState> {
+    Router::new()
+        .route("/contacts/export", get(handler_get_export))
+        .route(
+            "/contacts/export/vcard",
+            get(handler_get_export_vcard).post(handler_post_export_vcard),
+        )
+        .route(
+            "/contacts/export/pdf",
+            get(handler_get_export_pdf).post(handler_post_export_pdf),
+        )
+}
+
+/// Streams the whole view, without paging, as CSV, JSON or one of the address
+/// book formats (which ignore the column choice). The plain list is
+/// read from SQL a chunk at a time; views filtered in Rust are already in
+/// memory and are written out in the same chunks.
+pub async fn handler_get_export(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    ExtraQuery(params): ExtraQuery<ExportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_export", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let user_id = user.id();
+    let pool = state.pool_state.read().await.clone();
+    let (content_type, file_name) = match params.format_p {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "contacts.csv"),
+        ExportFormat::Json => ("application/json", "contacts.json"),
+        ExportFormat::Google => ("text/csv; charset=utf-8", "google-contacts.csv"),
+        ExportFormat::Outlook => ("text/csv; charset=utf-8", "outlook-contacts.csv"),
+        ExportFormat::Ldif => ("text/x-ldif; charset=utf-8", "contacts.ldif"),
+    };
+    let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Err(error) = write_export(&mut writer, pool, user_id, params).await {
+            println!("->> {} - EXPORT FAILED: {}", get_time(), error);
+        }
+    });
+    let body = axum::body::Body::from_stream(ReaderStream::new(reader));
+    let headers = [
+        (header::CONTENT_TYPE, content_type.to_owned()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file_name),
+        ),
+    ];
+    Ok((headers, body).into_response())
+}
+
+async fn write_export(
+    writer: &mut tokio::io::DuplexStream,
+    pool: Pool<Sqlite>,
+    user_id: i64,
+    params: ExportParams,
+) -> anyhow::Result<()> {
+    let search_bar = params.search_p.as_deref().unwrap_or("");
+    let sort_set = params.sort_p.as_deref().unwrap_or("");
+    let window_set = birthday_window(params.window_p);
+    let columns = export_columns(&params.columns_p);
+    let mut filtered = Contacts::filtered_contacts(
+        &pool,
+        user_id,
+        search_bar,
+        params.birthday_p,
+        sort_set,
+        window_set,
+    )
+    .await?
+    .map(|contacts_set| contacts_set.into_iter());
+    if params.format_p == ExportFormat::Json {
+        writer.write_all(b"[").await?;
+    }
+    let mut offset = 0;
+    loop {
+        let chunk = match filtered.as_mut() {
+            Some(contacts_set) => contacts_set.take(EXPORT_CHUNK as usize).collect(),
+            None => {
+                Contacts::search_contacts(
+                    &pool,
+                    user_id,
+                    search_bar,
+                    sort_set,
+                    EXPORT_CHUNK,
+                    offset,
+                )
+                .await?
+            }
+        };
+        if chunk.is_empty() && offset > 0 {
+            break;
+        }
+        let bytes = match params.format_p {
+            ExportFormat::Csv => export_csv(&chunk, &columns, offset == 0)?,
+            ExportFormat::Json => export_json(&chunk, &columns, offset == 0),
+            ExportFormat::Google => export_google_csv(&chunk, offset == 0)?,
+            ExportFormat::Outlook => export_outlook_csv(&chunk, offset == 0)?,
+            ExportFormat::Ldif => export_ldif(&chunk, offset == 0),
+        };
+        writer.write_all(&bytes).await?;
+        if chunk.is_empty() {
+            break;
+        }
+        offset += chunk.len() as i64;
+    }
+    if params.format_p == ExportFormat::Json {
+        writer.write_all(b"]").await?;
+    }
+    writer.shutdown().await?;
+    Ok(())
+}
+
+fn vcard_response(file_name: &str, contacts_set: &[Contact]) -> impl IntoResponse {
+    let body = contacts_set
+        .iter()
+        .map(|contact| contact.to_vcard())
+        .collect::<String>();
+    let headers = [
+        (header::CONTENT_TYPE, "text/vcard; charset=utf-8".to_owned()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file_name),
+        ),
+    ];
+    (headers, body)
+}
+
+pub async fn handler_get_export_vcard(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    Query(params): Query<ShowParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_export_vcard", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let contacts_set = Contacts::all_contacts(
+        &pool,
+        user.id(),
+        params.search_p.as_deref().unwrap_or(""),
+        params.birthday_p,
+        params.sort_p.as_deref().unwrap_or(""),
+        birthday_window(params.window_p),
+    )
+    .await?;
+    Ok(vcard_response("contacts.vcf", &contacts_set).into_response())
+}
+
+pub async fn handler_post_export_vcard(
+    messages: Messages,
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    ExtraForm(params_form): ExtraForm<DeleteBulkParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_export_vcard", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let ids_set = params_form
+        .ids_p
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|id| id.parse::<u32>().ok())
+        .collect::<Vec<u32>>();
+    let mut contacts_set: Vec<Contact> = vec![];
+    for id_set in ids_set {
+        if let Some(contact_set) = Contact::get_contact(&pool, id_set, user.id()).await? {
+            contacts_set.push(contact_set);
+        }
+    }
+    match contacts_set.is_empty() {
+        true => {
+            messages.error("Select at least one contact to export!");
+            Ok(Redirect::to("/contacts/show?page_p=1&birthday_p=0").into_response())
+        }
+        false => Ok(vcard_response("contacts.vcf", &contacts_set).into_response()),
+    }
+}
+
+fn pdf_response(layout: PdfLayout, contacts_set: &[Contact], messages: Messages) -> Response {
+    let no_labels = !contacts_set
+        .iter()
+        .any(|contact| !contact.address.trim().is_empty());
+    if layout == PdfLayout::Labels && no_labels {
+        messages.error("None of these contacts has an address to print on a label!");
+        return Redirect::to("/contacts/show?page_p=1&birthday_p=0").into_response();
+    }
+    let headers = [
+        (header::CONTENT_TYPE, "application/pdf".to_owned()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", layout.file_name()),
+        ),
+    ];
+    (headers, contacts_pdf(contacts_set, layout)).into_response()
+}
+
+/// The whole filtered view as a directory sheet or mailing labels.
+pub async fn handler_get_export_pdf(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    ExtraQuery(params): ExtraQuery<PdfParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_export_pdf", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let contacts_set = Contacts::all_contacts(
+        &pool,
+        user.id(),
+        params.search_p.as_deref().unwrap_or(""),
+        params.birthday_p,
+        params.sort_p.as_deref().unwrap_or(""),
+        birthday_window(params.window_p),
+    )
+    .await?;
+    Ok(pdf_response(params.layout_p, &contacts_set, messages))
+}
+
+/// The checked contacts as a directory sheet or mailing labels.
+pub async fn handler_post_export_pdf(
+    messages: Messages,
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    ExtraForm(params_form): ExtraForm<PdfSelectionParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_export_pdf", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let ids_set = params_form
+        .ids_p
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|id| id.parse::<u32>().ok())
+        .collect::<Vec<u32>>();
+    let mut contacts_set: Vec<Contact> = vec![];
+    for id_set in ids_set {
+        if let Some(contact_set) = Contact::get_contact(&pool, id_set, user.id()).await? {
+            contacts_set.push(contact_set);
+        }
+    }
+    match contacts_set.is_empty() {
+        true => {
+            messages.error("Select at least one contact to print!");
+            Ok(Redirect::to("/contacts/show?page_p=1&birthday_p=0").into_response())
+        }
+        false => Ok(pdf_response(params_form.layout_p, &contacts_set, messages)),
+    }
+}
+
+// endregion: EXPORT
+
 // region: CONTACTFORM (NEW/EDIT)
 #[derive(Template)]
 #[template(path = "contactform.html")]
 pub struct ContactFormTemplate {
     pub errors_t: CreationErrorState,
     pub contact: Contact,
-    pub archive_t: ArchiverState,
     username: Option<String>,
+    /// Fields filled in from a pasted signature that should be checked by hand.
+    uncertain_t: Vec<String>,
+    signature_t: String,
+}
+impl ContactFormTemplate {
+    fn uncertain(&self, field: &str) -> bool {
+        self.uncertain_t.iter().any(|uncertain| uncertain == field)
+    }
 }
 #[derive(Deserialize)]
 pub struct ContactIDParam {
     pub id_p: u32,
 }
 
+/// The new-contact form as typed so far, with the pasted signature to read.
+#[derive(Deserialize)]
+pub struct SignatureParams {
+    #[serde(default)]
+    pub first_name: String,
+    #[serde(default)]
+    pub last_name: String,
+    #[serde(default)]
+    pub phone: String,
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub birth_date: String,
+    #[serde(default)]
+    pub address: String,
+    #[serde(default)]
+    pub organization: String,
+    #[serde(default)]
+    pub signature_p: String,
+}
+
 pub fn contactform_new_router() -> Router<AppState> {
-    Router::new().route(
-        "/contacts/new",
-        get(handler_get_newcontact).post(handler_post_newcontact),
-    )
+    Router::new()
+        .route(
+            "/contacts/new",
+            get(handler_get_newcontact).post(handler_post_newcontact),
+        )
+        .route("/contacts/new/parse", post(handler_post_parse_signature))
 }
 
 pub async fn handler_get_newcontact(
@@ -351,20 +890,52 @@ pub async fn handler_get_newcontact(
     auth_session: AuthSession,
 ) -> Result<impl IntoResponse, AppError> {
     println!("->> {} - HANDLER: handler_get_newcontact", get_time());
-    let username: Option<String>;
-    if let Some(user) = auth_session.user {
-        username = Some(user.username);
-    } else {
-        username = None;
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login").into_response());
     };
+    let username = Some(user.username);
 
     let errors_all = state.contact_error_state.read().await.clone();
 
     let new_contact_templ = ContactFormTemplate {
         errors_t: errors_all,
         contact,
-        archive_t: state.archiver_state.read().await.clone(),
         username,
+        uncertain_t: Vec::new(),
+        signature_t: String::new(),
+    };
+    Ok(new_contact_templ.into_response())
+}
+
+/// Fills the empty fields of the new-contact form from a pasted signature;
+/// anything already typed stays.
+pub async fn handler_post_parse_signature(
+    auth_session: AuthSession,
+    Form(params): Form<SignatureParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_parse_signature", get_time());
+    let username = auth_session.user.map(|user| user.username);
+    let parsed = parse_signature(&params.signature_p);
+    let pick = |found: String, typed: String| match typed.trim().is_empty() {
+        true => found,
+        false => typed,
+    };
+    let contact = Contact {
+        first_name: pick(parsed.contact.first_name, params.first_name),
+        last_name: pick(parsed.contact.last_name, params.last_name),
+        phone: pick(parsed.contact.phone, params.phone),
+        email: pick(parsed.contact.email, params.email),
+        birth_date: params.birth_date,
+        address: pick(parsed.contact.address, params.address),
+        organization: pick(parsed.contact.organization, params.organization),
+        ..Default::default()
+    };
+    let new_contact_templ = ContactFormTemplate {
+        errors_t: CreationErrorState::default(),
+        contact,
+        username,
+        uncertain_t: parsed.uncertain,
+        signature_t: params.signature_p,
     };
     Ok(new_contact_templ.into_response())
 }
@@ -372,9 +943,14 @@ pub async fn handler_get_newcontact(
 pub async fn handler_post_newcontact(
     State(state): State<AppState>,
     messages: Messages,
-    Form(contact): Form<Contact>,
+    auth_session: AuthSession,
+    Form(mut contact): Form<Contact>,
 ) -> Result<Redirect, AppError> {
     println!("->> {} - HANDLER: handler_post_newcontact", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login"));
+    };
+    contact.user_id = Some(user.id());
     let pool = state.pool_state.read().await.clone();
     let new_error = contact.check_contact_errors(&pool).await?;
     match new_error {
@@ -388,10 +964,7 @@ pub async fn handler_post_newcontact(
         Some(new_error) => {
             let mut writable_state = state.contact_error_state.write().await;
             *writable_state = new_error;
-            let uri = format!(
-                "/contacts/new?id=0&first_name={}&last_name={}&phone={}&email={}&birth_date={}&time_creation=",
-                contact.first_name, contact.last_name, contact.phone, contact.email, contact.birth_date
-            );
+            let uri = format!("/contacts/new?{}", serde_urlencoded::to_string(&contact)?);
             Ok(Redirect::to(uri.as_str()))
         }
     }
@@ -411,30 +984,21 @@ pub async fn handler_get_editcontact(
     auth_session: AuthSession,
 ) -> Result<impl IntoResponse, AppError> {
     println!("->> {} - HANDLER: handler_get_editcontact", get_time());
-    let username: Option<String>;
-    if let Some(user) = auth_session.user {
-        username = Some(user.username);
-    } else {
-        username = None;
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login").into_response());
     };
+    let username = Some(user.username.clone());
 
     let pool = state.pool_state.read().await.clone();
-    let contact_set = sqlx::query_as!(
-        Contact,
-        r#"
-            SELECT *
-            FROM contacts_table
-            WHERE id = ?1
-            "#,
-        params.id_p
-    )
-    .fetch_one(&pool)
-    .await?;
+    let Some(contact_set) = Contact::get_contact(&pool, params.id_p, user.id()).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
     let edit_contact_template = ContactFormTemplate {
         errors_t: state.contact_error_state.read().await.clone(),
         contact: contact_set,
-        archive_t: state.archiver_state.read().await.clone(),
         username,
+        uncertain_t: Vec::new(),
+        signature_t: String::new(),
     };
     Ok(edit_contact_template.into_response())
 }
@@ -443,20 +1007,19 @@ pub async fn handler_post_editcontact(
     State(state): State<AppState>,
     auth_session: AuthSession,
     messages: Messages,
-    Form(contact): Form<Contact>,
+    Form(mut contact): Form<Contact>,
 ) -> Result<Redirect, AppError> {
     println!("->> {} - HANDLER: handler_post_editcontact", get_time());
-
-    match auth_session.user {
-        Some(user) => println!("user {}", user.username),
-        None => println!("none"),
-    }
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login"));
+    };
+    contact.user_id = Some(user.id());
 
     let pool = state.pool_state.read().await.clone();
     let new_error = contact.check_contact_errors(&pool).await?;
     match new_error {
         None => {
-            let (rows_affected, id) = contact.edit_contact(pool).await?;
+            let (rows_affected, id) = contact.edit_contact(pool, user.id()).await?;
             match rows_affected {
                 1 => messages.success(format!("Contact with id {} updated sucessfully!", id)),
                 _ => messages.error("Contact update failed!"),
@@ -489,131 +1052,2039 @@ pub fn utils_router() -> Router<AppState> {
         .route("/utils/close-flash", get(handler_close_flash))
 }
 
+/// Checks the email against the logged-in user's own contacts only.
 pub async fn handler_get_validate_email(
     State(state): State<AppState>,
     Query(params): Query<ValidateEmailParams>,
-) -> Result<String, AppError> {
-    println!("->> {} - HANDLER: handler_get_validate_email", get_time());
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_validate_email", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+
+    let pool = state.pool_state.read().await.clone();
+
+    let email_validated =
+        Contact::validate_email(&pool, params.email.as_str(), params.id_p, user.id()).await?;
+    Ok(email_validated.into_response())
+}
+
+pub async fn handler_get_count(
+    State(state): State<AppState>, //State(state_contacts): State<ContactState>
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_contacts_count", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+
+    let mut conn = pool.acquire().await?;
+    let contacts_count = Contacts::count_owned(&mut conn, user.id()).await?;
+    let span = format!("{} contacts", contacts_count);
+    //thread::sleep(Duration::from_millis(900));
+    Ok(span.into_response())
+}
+
+pub async fn handler_close_flash() -> Result<String, AppError> {
+    println!("->> {} - HANDLER: handler_close_flash", get_time());
+    let span = String::new();
+    Ok(span)
+}
+
+// endregion: UTILS
+
+// region: IMPORT
+
+#[derive(Template)]
+#[template(path = "import.html")]
+pub struct ImportTemplate {
+    pub rows_t: Vec<ImportRow>,
+    pub upload_t: String,
+    pub file_name_t: String,
+    pub messages_t: Vec<Message>,
+    pub username: Option<String>,
+}
+
+impl ImportTemplate {
+    pub fn valid_count(&self) -> usize {
+        self.rows_t
+            .iter()
+            .filter(|row| row.errors.is_empty())
+            .count()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ImportParams {
+    pub upload_p: String,
+}
+
+/// Phone exports with photos easily pass axum's 2 MB default.
+const IMPORT_SIZE_LIMIT: usize = 20 * 1024 * 1024;
+
+pub fn import_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/contacts/import",
+            get(handler_get_import).post(handler_post_import),
+        )
+        .route(
+            "/contacts/import/preview",
+            post(handler_post_import_preview),
+        )
+        .layer(DefaultBodyLimit::max(IMPORT_SIZE_LIMIT))
+}
+
+async fn parse_import(
+    source: &str,
+    user_id: i64,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<ImportRow>, AppError> {
+    let mut rows = VCard::parse_all(source)
+        .iter()
+        .map(ImportRow::from_vcard)
+        .collect::<Vec<ImportRow>>();
+    ImportRow::validate_all(&mut rows, user_id, pool).await?;
+    Ok(rows)
+}
+
+pub async fn handler_get_import(
+    messages: Messages,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_import", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/import").into_response());
+    };
+    let import_tmpl = ImportTemplate {
+        rows_t: Vec::new(),
+        upload_t: String::new(),
+        file_name_t: String::new(),
+        messages_t: messages.into_iter().collect(),
+        username: Some(user.username),
+    };
+    Ok(import_tmpl.into_response())
+}
+
+/// Parses the uploaded file and shows every record with its errors; nothing is
+/// written until the preview is confirmed. CSV and LDIF files are recognised
+/// and sent on to the column mapping instead.
+pub async fn handler_post_import_preview(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_import_preview", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/import").into_response());
+    };
+    let mut bytes = Vec::new();
+    let mut file_name = String::new();
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() == Some("file_p") {
+            file_name = field.file_name().unwrap_or("").to_owned();
+            bytes = field.bytes().await?.to_vec();
+        }
+    }
+    let source = String::from_utf8_lossy(&bytes).into_owned();
+    let user_id = user.id();
+    let username = Some(user.username);
+    if ContactFileFormat::detect(&source) != ContactFileFormat::VCard {
+        return csv_mapping_response(&bytes, file_name, messages, username);
+    }
+    let pool = state.pool_state.read().await.clone();
+    vcard_preview_response(source, file_name, user_id, &pool, messages, username).await
+}
+
+async fn vcard_preview_response(
+    source: String,
+    file_name: String,
+    user_id: i64,
+    pool: &Pool<Sqlite>,
+    messages: Messages,
+    username: Option<String>,
+) -> Result<Response, AppError> {
+    let rows = parse_import(&source, user_id, pool).await?;
+    if rows.is_empty() {
+        messages.error("No contacts found in that file!");
+        return Ok(Redirect::to("/contacts/import").into_response());
+    }
+    let import_tmpl = ImportTemplate {
+        rows_t: rows,
+        upload_t: stash_upload(source.as_bytes()).await?,
+        file_name_t: file_name,
+        messages_t: messages.into_iter().collect(),
+        username,
+    };
+    Ok(import_tmpl.into_response())
+}
+
+pub async fn handler_post_import(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    Form(params): Form<ImportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_import", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/import"));
+    };
+    let Some(bytes) = read_upload(&params.upload_p).await? else {
+        messages.error("That preview has expired, please upload the file again!");
+        return Ok(Redirect::to("/contacts/import"));
+    };
+    let pool = state.pool_state.read().await.clone();
+    let user_id = user.id();
+    let rows = parse_import(&String::from_utf8_lossy(&bytes), user_id, &pool).await?;
+    let inserted = ImportRow::import_valid(&rows, user_id, &pool).await?;
+    remove_upload(&params.upload_p).await?;
+    let skipped = rows.len() as u32 - inserted;
+    match (inserted, skipped) {
+        (0, _) => messages.error("Nothing imported, every contact had errors!"),
+        (_, 0) => messages.success(format!("{} contacts imported successfully!", inserted)),
+        (_, _) => messages.success(format!(
+            "{} contacts imported successfully, {} skipped!",
+            inserted, skipped
+        )),
+    };
+    Ok(Redirect::to("/contacts/show?page_p=1&birthday_p=0"))
+}
+
+// endregion: IMPORT
+
+// region: CSV IMPORT
+
+#[derive(Template)]
+#[template(path = "csv_import.html")]
+pub struct CsvImportTemplate {
+    pub headers_t: Vec<String>,
+    pub mapping_t: CsvMapping,
+    pub rows_count_t: usize,
+    pub source_t: String,
+    pub delimiter_t: u8,
+    pub encoding_t: String,
+    /// The detected layout, e.g. "Outlook CSV".
+    pub format_t: String,
+    pub file_name_t: String,
+    pub messages_t: Vec<Message>,
+    pub username: Option<String>,
+}
+
+impl CsvImportTemplate {
+    pub fn fields(&self) -> Vec<(&'static str, &'static str)> {
+        CSV_FIELDS
+            .iter()
+            .map(|(key, label, _)| (*key, *label))
+            .collect()
+    }
+    pub fn selected(&self, field: &str, column: &usize) -> bool {
+        self.mapping_t.column(field) == Some(*column)
+    }
+    pub fn delimiter_name(&self) -> String {
+        delimiter_name(self.delimiter_t)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "csv_preview.html")]
+pub struct CsvPreviewTemplate {
+    pub rows_t: Vec<CsvReportRow>,
+    pub rows_count_t: usize,
+}
+
+impl CsvPreviewTemplate {
+    pub fn count(&self, outcome: &str) -> usize {
+        self.rows_t
+            .iter()
+            .filter(|row| row.outcome.name() == outcome)
+            .count()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "csv_import_job.html")]
+pub struct CsvImportJobTemplate {
+    pub job_id_t: u32,
+    pub job_t: CsvImportJob,
+}
+
+/// The mapping form: the decoded file, its delimiter, one column per field
+/// (empty to leave the field blank) and the duplicate strategy.
+#[derive(Deserialize)]
+pub struct CsvImportParams {
+    pub source_p: String,
+    pub delimiter_p: u8,
+    pub map_first_name_p: Option<String>,
+    pub map_last_name_p: Option<String>,
+    pub map_phone_p: Option<String>,
+    pub map_email_p: Option<String>,
+    pub map_birth_date_p: Option<String>,
+    pub map_address_p: Option<String>,
+    pub map_organization_p: Option<String>,
+    pub strategy_p: DuplicateStrategy,
+}
+
+impl CsvImportParams {
+    pub fn mapping(&self) -> CsvMapping {
+        let column = |value: &Option<String>| value.as_deref().and_then(|v| v.parse().ok());
+        CsvMapping {
+            columns: [
+                column(&self.map_first_name_p),
+                column(&self.map_last_name_p),
+                column(&self.map_phone_p),
+                column(&self.map_email_p),
+                column(&self.map_birth_date_p),
+                column(&self.map_address_p),
+                column(&self.map_organization_p),
+            ],
+            format: ContactFileFormat::detect(&self.source_p),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CsvJobParams {
+    pub job_p: u32,
+}
+
+pub fn csv_import_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/contacts/import/csv",
+            get(handler_get_csv_import).post(handler_post_csv_import),
+        )
+        .route(
+            "/contacts/import/csv/mapping",
+            post(handler_post_csv_mapping),
+        )
+        .route(
+            "/contacts/import/csv/preview",
+            post(handler_post_csv_preview),
+        )
+        .route("/contacts/import/csv/job", get(handler_get_csv_job))
+        .route("/contacts/import/csv/report", get(handler_get_csv_report))
+        .layer(DefaultBodyLimit::max(IMPORT_SIZE_LIMIT))
+}
+
+pub async fn handler_get_csv_import(
+    messages: Messages,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_csv_import", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/import/csv").into_response());
+    };
+    let csv_import_tmpl = CsvImportTemplate {
+        headers_t: Vec::new(),
+        mapping_t: CsvMapping::default(),
+        rows_count_t: 0,
+        source_t: String::new(),
+        delimiter_t: b',',
+        encoding_t: String::new(),
+        format_t: String::new(),
+        file_name_t: String::new(),
+        messages_t: messages.into_iter().collect(),
+        username: Some(user.username),
+    };
+    Ok(csv_import_tmpl.into_response())
+}
+
+/// Detects the format of the upload; vCards go to their own preview, the
+/// rest is decoded and gets its column mapping guessed.
+pub async fn handler_post_csv_mapping(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_csv_mapping", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/import/csv").into_response());
+    };
+    let mut bytes = Vec::new();
+    let mut file_name = String::new();
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() == Some("file_p") {
+            file_name = field.file_name().unwrap_or("").to_owned();
+            bytes = field.bytes().await?.to_vec();
+        }
+    }
+    let user_id = user.id();
+    let username = Some(user.username);
+    let (source, _) = decode_csv(&bytes);
+    if ContactFileFormat::detect(&source) == ContactFileFormat::VCard {
+        let pool = state.pool_state.read().await.clone();
+        return vcard_preview_response(source, file_name, user_id, &pool, messages, username).await;
+    }
+    csv_mapping_response(&bytes, file_name, messages, username)
+}
+
+/// The mapping form for a CSV or LDIF upload. LDIF entries are turned into
+/// CSV rows here, so the later steps only ever see CSV.
+fn csv_mapping_response(
+    bytes: &[u8],
+    file_name: String,
+    messages: Messages,
+    username: Option<String>,
+) -> Result<Response, AppError> {
+    let (mut source, encoding) = decode_csv(bytes);
+    let format = ContactFileFormat::detect(&source);
+    let mut delimiter = detect_delimiter(&source);
+    let read = match format {
+        ContactFileFormat::Ldif => {
+            let (headers, records) = read_ldif(&source);
+            delimiter = b',';
+            source = write_csv(&headers, &records)?;
+            Ok((headers, records))
+        }
+        _ => read_csv(&source, delimiter),
+    };
+    let (headers, records) = match read {
+        Ok(read) => read,
+        Err(error) => {
+            messages.error(format!("That file is not valid CSV: {}", error));
+            return Ok(Redirect::to("/contacts/import/csv").into_response());
+        }
+    };
+    if records.is_empty() {
+        messages.error("No rows found below the header of that file!");
+        return Ok(Redirect::to("/contacts/import/csv").into_response());
+    }
+    let csv_import_tmpl = CsvImportTemplate {
+        mapping_t: CsvMapping::guess(&headers, format),
+        headers_t: headers,
+        rows_count_t: records.len(),
+        source_t: source,
+        delimiter_t: delimiter,
+        encoding_t: encoding.to_owned(),
+        format_t: format.name().to_owned(),
+        file_name_t: file_name,
+        messages_t: messages.into_iter().collect(),
+        username,
+    };
+    Ok(csv_import_tmpl.into_response())
+}
+
+/// Dry run of the first rows: the import runs in a transaction that is rolled
+/// back, so the outcomes shown are exactly what the import would do.
+pub async fn handler_post_csv_preview(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    Form(params): Form<CsvImportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_csv_preview", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let (_, records) = read_csv(&params.source_p, params.delimiter_p)?;
+    let mapping = params.mapping();
+    let user_id = user.id();
+    let mut rows = Vec::new();
+    let mut tx = pool.begin().await?;
+    for (index, record) in records.iter().take(CSV_PREVIEW_ROWS).enumerate() {
+        let row = mapping
+            .apply_row(index + 2, record, params.strategy_p, user_id, &mut tx)
+            .await?;
+        rows.push(row);
+    }
+    tx.rollback().await?;
+    let csv_preview_tmpl = CsvPreviewTemplate {
+        rows_t: rows,
+        rows_count_t: records.len(),
+    };
+    Ok(csv_preview_tmpl.into_response())
+}
+
+pub async fn handler_post_csv_import(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    Form(params): Form<CsvImportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_csv_import", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let (_, records) = read_csv(&params.source_p, params.delimiter_p)?;
+    CsvImportJob::evict_finished(&state.csv_import_state).await;
+    let user_id = user.id();
+    let job_id = rand::random::<u32>();
+    let job = CsvImportJob {
+        status: "Running".to_owned(),
+        total: records.len(),
+        user_id,
+        ..Default::default()
+    };
+    state
+        .csv_import_state
+        .write()
+        .await
+        .insert(job_id, job.clone());
+    let _handle = tokio::spawn(run_csv_import(
+        state.csv_import_state.clone(),
+        job_id,
+        pool,
+        records,
+        params.mapping(),
+        params.strategy_p,
+        user_id,
+    ));
+    let csv_job_tmpl = CsvImportJobTemplate {
+        job_id_t: job_id,
+        job_t: job,
+    };
+    Ok(csv_job_tmpl.into_response())
+}
+
+pub async fn handler_get_csv_job(
+    State(csv_import_state): State<CsvImportStateType>,
+    auth_session: AuthSession,
+    Query(params): Query<CsvJobParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_csv_job", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let user_id = user.id();
+    match csv_import_state.read().await.get(&params.job_p) {
+        Some(job) if job.user_id == user_id => {
+            let csv_job_tmpl = CsvImportJobTemplate {
+                job_id_t: params.job_p,
+                job_t: job.clone(),
+            };
+            Ok(csv_job_tmpl.into_response())
+        }
+        _ => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+pub async fn handler_get_csv_report(
+    State(csv_import_state): State<CsvImportStateType>,
+    auth_session: AuthSession,
+    Query(params): Query<CsvJobParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_csv_report", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let user_id = user.id();
+    let Some(job) = csv_import_state
+        .read()
+        .await
+        .get(&params.job_p)
+        .filter(|job| job.user_id == user_id)
+        .cloned()
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let headers = [
+        (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+        (
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"import-report.csv\"",
+        ),
+    ];
+    Ok((headers, job.report_csv()?).into_response())
+}
+
+// endregion: CSV IMPORT
+
+// region: MAIL IMPORT
+
+#[derive(Template)]
+#[template(path = "mail_import.html")]
+pub struct MailImportTemplate {
+    pub correspondents_t: Vec<Correspondent>,
+    pub files_count_t: usize,
+    pub messages_count_t: usize,
+    pub messages_t: Vec<Message>,
+    pub username: Option<String>,
+}
+
+impl MailImportTemplate {
+    pub fn new_count(&self) -> usize {
+        self.correspondents_t
+            .iter()
+            .filter(|correspondent| !correspondent.existing)
+            .count()
+    }
+}
+
+/// The review list: one entry per row in every list, `pick_p` holds the
+/// indexes of the ticked rows.
+#[derive(Deserialize)]
+pub struct MailImportParams {
+    #[serde(default)]
+    pub pick_p: Vec<usize>,
+    #[serde(default)]
+    pub first_name_p: Vec<String>,
+    #[serde(default)]
+    pub last_name_p: Vec<String>,
+    #[serde(default)]
+    pub email_p: Vec<String>,
+    #[serde(default)]
+    pub phone_p: Vec<String>,
+    #[serde(default)]
+    pub birth_date_p: Vec<String>,
+}
+
+/// Whole mailboxes are much larger than address book exports.
+const MAIL_IMPORT_SIZE_LIMIT: usize = 200 * 1024 * 1024;
+
+pub fn mail_import_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/contacts/import/mail",
+            get(handler_get_mail_import).post(handler_post_mail_import),
+        )
+        .route(
+            "/contacts/import/mail/preview",
+            post(handler_post_mail_preview),
+        )
+        .layer(DefaultBodyLimit::max(MAIL_IMPORT_SIZE_LIMIT))
+}
+
+pub async fn handler_get_mail_import(
+    messages: Messages,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_mail_import", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/import/mail").into_response());
+    };
+    let mail_tmpl = MailImportTemplate {
+        correspondents_t: Vec::new(),
+        files_count_t: 0,
+        messages_count_t: 0,
+        messages_t: messages.into_iter().collect(),
+        username: Some(user.username),
+    };
+    Ok(mail_tmpl.into_response())
+}
+
+/// Reads the headers of every uploaded message and lists the correspondents;
+/// nothing is created until the review is submitted.
+pub async fn handler_post_mail_preview(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_mail_preview", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/import/mail").into_response());
+    };
+    let mut files = Vec::new();
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() == Some("files_p") {
+            let bytes = field.bytes().await?;
+            if !bytes.is_empty() {
+                files.push(bytes.to_vec());
+            }
+        }
+    }
+    let files_count = files.len();
+    let (mut correspondents, messages_count) = tokio::task::spawn_blocking(move || {
+        let messages_raw = files
+            .iter()
+            .flat_map(|bytes| mailbox_messages(bytes))
+            .collect::<Vec<Vec<u8>>>();
+        read_correspondents(&messages_raw)
+    })
+    .await?;
+    if correspondents.is_empty() {
+        messages.error("No email addresses found in those files!");
+        return Ok(Redirect::to("/contacts/import/mail").into_response());
+    }
+    let pool = state.pool_state.read().await.clone();
+    Correspondent::mark_existing(&mut correspondents, user.id(), &pool).await?;
+    let mail_tmpl = MailImportTemplate {
+        correspondents_t: correspondents,
+        files_count_t: files_count,
+        messages_count_t: messages_count,
+        messages_t: messages.into_iter().collect(),
+        username: Some(user.username),
+    };
+    Ok(mail_tmpl.into_response())
+}
+
+/// Creates the ticked correspondents that pass `check_contact_errors`, so
+/// nothing added since the review is duplicated; skipped rows are flashed
+/// with their errors.
+pub async fn handler_post_mail_import(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    ExtraForm(params): ExtraForm<MailImportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_mail_import", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/import/mail"));
+    };
+    let pool = state.pool_state.read().await.clone();
+    let user_id = user.id();
+    let mut emails = HashSet::new();
+    let mut contacts_set = Vec::new();
+    let mut skipped = Vec::new();
+    for index in params.pick_p {
+        let field = |values: &[String]| values.get(index).map(|value| value.trim().to_owned());
+        let (Some(first_name), Some(last_name), Some(email)) = (
+            field(&params.first_name_p),
+            field(&params.last_name_p),
+            field(&params.email_p),
+        ) else {
+            continue;
+        };
+        let contact = Contact {
+            first_name,
+            last_name,
+            phone: field(&params.phone_p).unwrap_or_default(),
+            email,
+            birth_date: field(&params.birth_date_p).unwrap_or_default(),
+            user_id: Some(user_id),
+            ..Default::default()
+        };
+        let mut errors = match contact.check_contact_errors(&pool).await? {
+            Some(errors) => errors.messages(),
+            None => Vec::new(),
+        };
+        if errors.is_empty() && !emails.insert(contact.email.to_lowercase()) {
+            errors.push("Ticked twice".to_owned());
+        }
+        if !errors.is_empty() {
+            skipped.push(format!("{}: {}", contact.email, errors.join(", ")));
+            continue;
+        }
+        contacts_set.push(contact);
+    }
+    let mut tx = pool.begin().await?;
+    for contact in &contacts_set {
+        contact.insert_contact(&mut tx).await?;
+    }
+    tx.commit().await?;
+    let skipped_count = skipped.len();
+    let messages = skipped
+        .into_iter()
+        .fold(messages, |messages, row| messages.error(row));
+    match (contacts_set.len(), skipped_count) {
+        (0, 0) => messages.error("Tick at least one correspondent to create!"),
+        (0, _) => messages.error("Nothing created, every ticked row had errors!"),
+        (created, 0) => messages.success(format!("{} contacts created!", created)),
+        (created, _) => messages.success(format!(
+            "{} contacts created, {} skipped with errors!",
+            created, skipped_count
+        )),
+    };
+    Ok(Redirect::to("/contacts/show?page_p=1&birthday_p=0"))
+}
+
+// endregion: MAIL IMPORT
+
+// region: SAVED SEARCHES
+
+#[derive(Template)]
+#[template(path = "saved_searches.html")]
+pub struct SavedSearchesTemplate {
+    pub searches_t: Vec<(SavedSearch, u32)>,
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SaveSearchParams {
+    pub name_p: String,
+    pub search_p: Option<String>,
+    pub birthday_p: u32,
+    pub sort_p: Option<String>,
+    pub window_p: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct SearchIDParam {
+    pub id_p: u32,
+}
+
+pub fn saved_searches_router() -> Router<AppState> {
+    Router::new().route(
+        "/contacts/searches",
+        get(handler_get_searches)
+            .post(handler_post_search)
+            .delete(handler_delete_search),
+    )
+}
+
+async fn render_searches(
+    pool: &Pool<Sqlite>,
+    user: Option<User>,
+) -> Result<SavedSearchesTemplate, AppError> {
+    let mut searches_set = Vec::new();
+    let mut username = None;
+    if let Some(user) = user {
+        let searches = SavedSearch::list_searches(pool, user.id()).await?;
+        let counts = SavedSearch::counts(pool, user.id(), &searches).await?;
+        searches_set = searches.into_iter().zip(counts).collect();
+        username = Some(user.username);
+    }
+    Ok(SavedSearchesTemplate {
+        searches_t: searches_set,
+        username,
+    })
+}
+
+pub async fn handler_get_searches(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_searches", get_time());
+    let pool = state.pool_state.read().await.clone();
+    let searches_tmpl = render_searches(&pool, auth_session.user).await?;
+    Ok(searches_tmpl.into_response())
+}
+
+pub async fn handler_post_search(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    Form(params): Form<SaveSearchParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_search", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let name = params.name_p.trim();
+    if !name.is_empty() {
+        let show_params = ShowParams {
+            search_p: params.search_p.filter(|search| !search.is_empty()),
+            page_p: 1,
+            birthday_p: params.birthday_p,
+            sort_p: params.sort_p.filter(|sort| !sort.is_empty()),
+            window_p: params.window_p.filter(|_| params.birthday_p != 0),
+            index_p: None,
+        };
+        let search = SavedSearch {
+            user_id: user.id(),
+            name: name.to_owned(),
+            query: serde_urlencoded::to_string(&show_params)?,
+            ..Default::default()
+        };
+        search.create_search(pool.clone()).await?;
+    }
+    let searches_tmpl = render_searches(&pool, Some(user)).await?;
+    Ok(searches_tmpl.into_response())
+}
+
+pub async fn handler_delete_search(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    Query(params): Query<SearchIDParam>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_delete_search", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    SavedSearch::delete_search(pool.clone(), params.id_p, user.id()).await?;
+    let searches_tmpl = render_searches(&pool, Some(user)).await?;
+    Ok(searches_tmpl.into_response())
+}
+
+// endregion: SAVED SEARCHES
+
+// region: ARCHIVE
+
+#[derive(Template)]
+#[template(path = "archive_ui.html")]
+pub struct ArchiveUiTemplate {
+    pub job_t: Option<ArchiveJob>,
+}
+
+impl ArchiveUiTemplate {
+    /// "Waiting" when there is no job to show.
+    pub fn status(&self) -> String {
+        self.job_t
+            .as_ref()
+            .map(|job| job.status.clone())
+            .unwrap_or_else(|| "Waiting".to_owned())
+    }
+    pub fn progress(&self) -> f64 {
+        self.job_t.as_ref().map(|job| job.progress).unwrap_or(0.0)
+    }
+    pub fn job_id(&self) -> i64 {
+        self.job_t.as_ref().map(|job| job.id).unwrap_or(0)
+    }
+}
+
+/// The progress bar alone, streamed to the widget while the job runs.
+#[derive(Template)]
+#[template(path = "archive_progress.html")]
+pub struct ArchiveProgressTemplate {
+    pub progress_t: f64,
+}
+
+impl ArchiveProgressTemplate {
+    pub fn progress(&self) -> f64 {
+        self.progress_t
+    }
+}
+
+/// How often the events stream looks at a running job.
+const ARCHIVE_EVENTS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+/// How long the browser waits before reconnecting once the stream has ended.
+const ARCHIVE_EVENTS_RETRY: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Template)]
+#[template(path = "archives.html")]
+pub struct ArchivesTemplate {
+    pub jobs_t: Vec<ArchiveJob>,
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveIDParam {
+    pub id_p: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ArchivePassphraseParams {
+    #[serde(default)]
+    pub passphrase_p: String,
+}
+
+pub fn archive_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/contacts/archive",
+            get(handler_get_archive).post(handler_post_archive),
+        )
+        .route(
+            "/contacts/archive/file",
+            get(handler_get_archive_file).delete(handler_delete_archive_file),
+        )
+        .route(
+            "/contacts/archive/download",
+            get(handler_get_archive_download),
+        )
+        .route("/contacts/archive/events", get(handler_get_archive_events))
+        .route(
+            "/contacts/archive/cancel",
+            post(handler_post_archive_cancel),
+        )
+        .route(
+            "/contacts/archives",
+            get(handler_get_archives).delete(handler_delete_archives),
+        )
+}
+
+pub async fn handler_get_archive(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_archive", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let archive_ui_tmpl = ArchiveUiTemplate {
+        job_t: ArchiveJob::latest(&pool, user.id()).await?,
+    };
+    Ok(archive_ui_tmpl.into_response())
+}
+
+/// Sends the owner of a finished archive (the newest one without `id_p`) to a
+/// freshly signed download link.
+pub async fn handler_get_archive_file(
+    State(state): State<AppState>,
+    Query(params): Query<ArchiveIDParam>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_archive_file", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let job = match params.id_p {
+        Some(id) => ArchiveJob::get(&pool, id, user.id()).await?,
+        None => ArchiveJob::latest(&pool, user.id()).await?,
+    };
+    match job {
+        Some(job) if job.status == "Complete" => {
+            let link = ArchiveLink::sign(user.id(), &job.file_name);
+            Ok(Redirect::to(&link.url()?).into_response())
+        }
+        _ => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+pub async fn handler_get_archive_download(
+    State(state): State<AppState>,
+    Query(link): Query<ArchiveLink>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_archive_download", get_time());
+    if !link.verify() {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Ok(file) = tokio::fs::File::open(link.path()).await else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    let pool = state.pool_state.read().await.clone();
+    link.record_download(&pool, user_agent).await?;
+    let content_type = if link.file_p.ends_with(ENCRYPTED_ARCHIVE_EXTENSION) {
+        "application/octet-stream"
+    } else {
+        "application/json"
+    };
+    let stream = ReaderStream::new(file);
+    let body = axum::body::Body::from_stream(stream);
+    let headers = [
+        (header::CONTENT_TYPE, content_type.to_owned()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", link.file_p),
+        ),
+    ];
+    Ok((headers, body).into_response())
+}
+
+/// Starts an archive, encrypted when a passphrase is given. The passphrase
+/// only lives in memory until the job is done.
+pub async fn handler_post_archive(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    Form(params): Form<ArchivePassphraseParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_archive", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let passphrase = Some(params.passphrase_p).filter(|passphrase| !passphrase.is_empty());
+    let (job, started) = ArchiveJob::start(&pool, user.id(), passphrase.is_some()).await?;
+    if started {
+        let job = job.clone();
+        let pool = pool.clone();
+        let _handle = tokio::spawn(async move {
+            run_thread(pool, job, user.username, passphrase).await;
+        });
+    }
+    let archive_ui_tmpl = ArchiveUiTemplate { job_t: Some(job) };
+    Ok(archive_ui_tmpl.into_response())
+}
+
+/// Streams a job's progress bar as `progress` events, then one `done` event
+/// once the job stops running, on which the widget reloads itself.
+pub async fn handler_get_archive_events(
+    State(state): State<AppState>,
+    Query(params): Query<ArchiveIDParam>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_archive_events", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let Some(job) = ArchiveJob::get(&pool, params.id_p.unwrap_or(0), user.id()).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let user_id = user.id();
+    let updates = stream::unfold(Some(job), move |job| {
+        let pool = pool.clone();
+        async move {
+            let job = job?;
+            if job.status != "Running" {
+                // The widget reloads on this event and closes the source; the
+                // long retry keeps the browser from reconnecting before that.
+                let event = Event::default()
+                    .event("done")
+                    .data(job.status)
+                    .retry(ARCHIVE_EVENTS_RETRY);
+                return Some((Ok::<Event, Infallible>(event), None));
+            }
+            let progress_tmpl = ArchiveProgressTemplate {
+                progress_t: job.progress,
+            };
+            let event = Event::default()
+                .event("progress")
+                .data(progress_tmpl.render().unwrap_or_default());
+            tokio::time::sleep(ARCHIVE_EVENTS_INTERVAL).await;
+            let next = ArchiveJob::get(&pool, job.id, user_id).await.ok().flatten();
+            Some((Ok(event), next))
+        }
+    });
+    Ok(Sse::new(updates)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// Stops the user's running job; the widget shows it as cancelled.
+pub async fn handler_post_archive_cancel(
+    State(state): State<AppState>,
+    Query(params): Query<ArchiveIDParam>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_archive_cancel", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    ArchiveJob::cancel(&pool, params.id_p.unwrap_or(0), user.id()).await?;
+    let archive_ui_tmpl = ArchiveUiTemplate {
+        job_t: ArchiveJob::latest(&pool, user.id()).await?,
+    };
+    Ok(archive_ui_tmpl.into_response())
+}
+
+/// Clears the header widget; the archive stays on the archives page.
+pub async fn handler_delete_archive_file(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_delete_archive_file", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    ArchiveJob::dismiss(&pool, user.id()).await?;
+    let archive_ui_tmpl = ArchiveUiTemplate {
+        job_t: ArchiveJob::latest(&pool, user.id()).await?,
+    };
+    Ok(archive_ui_tmpl.into_response())
+}
+
+pub async fn handler_get_archives(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_archives", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/archives").into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let archives_tmpl = ArchivesTemplate {
+        jobs_t: ArchiveJob::list(&pool, user.id()).await?,
+        username: Some(user.username),
+    };
+    Ok(archives_tmpl.into_response())
+}
+
+pub async fn handler_delete_archives(
+    State(state): State<AppState>,
+    Query(params): Query<ArchiveIDParam>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_delete_archives", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    if let Some(job) = ArchiveJob::get(&pool, params.id_p.unwrap_or(0), user.id()).await? {
+        job.delete(&pool).await?;
+    }
+    Ok(([("HX-Trigger", "archives_changed")], "").into_response())
+}
+
+// endregion: ARCHIVE
+
+// region: ADMIN
+
+#[derive(Template)]
+#[template(path = "admin_backups.html")]
+pub struct AdminBackupsTemplate {
+    pub backups_t: Vec<Backup>,
+    pub policy_t: BackupPolicy,
+    pub messages_t: Vec<Message>,
+    pub username: Option<String>,
+}
+
+impl AdminBackupsTemplate {
+    /// The newest backup, whatever its outcome.
+    pub fn last(&self) -> Option<&Backup> {
+        self.backups_t.first()
+    }
+    pub fn next_run(&self) -> String {
+        self.policy_t
+            .next_run()
+            .map(|next| next.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "not scheduled".to_owned())
+    }
+}
+
+pub fn admin_router() -> Router<AppState> {
+    Router::new().route(
+        "/admin/backups",
+        get(handler_get_admin_backups).post(handler_post_admin_backups),
+    )
+}
+
+pub async fn handler_get_admin_backups(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_admin_backups", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/admin/backups").into_response());
+    };
+    if !is_admin(&user.username) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let pool = state.pool_state.read().await.clone();
+    let admin_backups_tmpl = AdminBackupsTemplate {
+        backups_t: Backup::list(&pool).await?,
+        policy_t: BackupPolicy::from_env()?,
+        messages_t: messages.into_iter().collect(),
+        username: Some(user.username),
+    };
+    Ok(admin_backups_tmpl.into_response())
+}
+
+/// Takes a backup right away, outside the schedule.
+pub async fn handler_post_admin_backups(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_admin_backups", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    if !is_admin(&user.username) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let pool = state.pool_state.read().await.clone();
+    let backup = run_backup(&pool, &BackupPolicy::from_env()?, "manual").await?;
+    if backup.status == "Ok" {
+        messages.success(format!("Backup {} written and checked!", backup.file_name));
+    } else {
+        messages.error(format!("Backup failed: {}", backup.message));
+    }
+    Ok(Redirect::to("/admin/backups").into_response())
+}
+
+// endregion: ADMIN
+
+// region: RESTORE
+
+#[derive(Template)]
+#[template(path = "restore.html")]
+pub struct RestoreTemplate {
+    pub summary_t: Option<RestoreSummary>,
+    pub upload_t: String,
+    /// The passphrase is asked for again to confirm an encrypted archive.
+    pub encrypted_t: bool,
+    pub file_name_t: String,
+    pub messages_t: Vec<Message>,
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreParams {
+    pub upload_p: String,
+    #[serde(default)]
+    pub passphrase_p: String,
+    #[serde(default)]
+    pub mode_p: RestoreMode,
+}
+
+pub fn restore_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/contacts/restore",
+            get(handler_get_restore).post(handler_post_restore),
+        )
+        .route(
+            "/contacts/restore/preview",
+            post(handler_post_restore_preview),
+        )
+        .layer(DefaultBodyLimit::max(IMPORT_SIZE_LIMIT))
+}
+
+/// Turns away every write but logging in while a restore runs.
+pub async fn maintenance_guard(
+    State(maintenance_state): State<MaintenanceStateType>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let read_only = matches!(*request.method(), Method::GET | Method::HEAD);
+    if !read_only && request.uri().path() != "/login" && *maintenance_state.read().await {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "A restore is running; the contacts are read-only for a moment.",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+pub async fn handler_get_restore(
+    messages: Messages,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_restore", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/restore").into_response());
+    };
+    let restore_tmpl = RestoreTemplate {
+        summary_t: None,
+        upload_t: String::new(),
+        encrypted_t: false,
+        file_name_t: String::new(),
+        messages_t: messages.into_iter().collect(),
+        username: Some(user.username),
+    };
+    Ok(restore_tmpl.into_response())
+}
+
+/// Decrypts and checks the uploaded archive and shows what it holds; nothing
+/// is written until the restore is confirmed. The uploaded file stays in the
+/// upload stash as it came, still encrypted, and the form only carries its id.
+pub async fn handler_post_restore_preview(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_restore_preview", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let mut content = Vec::new();
+    let mut passphrase = String::new();
+    let mut file_name = String::new();
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("file_p") => {
+                file_name = field.file_name().unwrap_or("").to_owned();
+                content = field.bytes().await?.to_vec();
+            }
+            Some("passphrase_p") => passphrase = field.text().await?,
+            _ => {}
+        }
+    }
+    let encrypted = is_encrypted_archive(&content);
+    let (content, opened) = tokio::task::spawn_blocking(move || {
+        let opened = open_archive(&content, &passphrase);
+        (content, opened)
+    })
+    .await?;
+    let json = match opened {
+        Ok(json) => json,
+        Err(error) => {
+            messages.error(error.to_string());
+            return Ok(Redirect::to("/contacts/restore").into_response());
+        }
+    };
+    let pool = state.pool_state.read().await.clone();
+    let archive = match ContactsArchive::parse(&json, &pool, &user.username).await {
+        Ok(archive) => archive,
+        Err(error) => {
+            messages.error(error.to_string());
+            return Ok(Redirect::to("/contacts/restore").into_response());
+        }
+    };
+    let restore_tmpl = RestoreTemplate {
+        summary_t: Some(archive.summary(&pool, user.id()).await?),
+        // The file is kept as uploaded, so an encrypted archive never sits
+        // decrypted on disk.
+        upload_t: stash_upload(&content).await?,
+        encrypted_t: encrypted,
+        file_name_t: file_name,
+        messages_t: messages.into_iter().collect(),
+        username: Some(user.username),
+    };
+    Ok(restore_tmpl.into_response())
+}
+
+/// Takes a safety backup of the database, then restores the archive with the
+/// app read-only until it is done.
+pub async fn handler_post_restore(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    Form(params): Form<RestoreParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_restore", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let Some(content) = read_upload(&params.upload_p).await? else {
+        messages.error("That preview has expired, please upload the archive again!");
+        return Ok(Redirect::to("/contacts/restore").into_response());
+    };
+    remove_upload(&params.upload_p).await?;
+    let passphrase = params.passphrase_p;
+    let opened = tokio::task::spawn_blocking(move || open_archive(&content, &passphrase)).await?;
+    let json = match opened {
+        Ok(json) => json,
+        Err(error) => {
+            messages.error(error.to_string());
+            return Ok(Redirect::to("/contacts/restore").into_response());
+        }
+    };
+    let pool = state.pool_state.read().await.clone();
+    let archive = match ContactsArchive::parse(&json, &pool, &user.username).await {
+        Ok(archive) => archive,
+        Err(error) => {
+            messages.error(error.to_string());
+            return Ok(Redirect::to("/contacts/restore").into_response());
+        }
+    };
+    {
+        let mut maintenance = state.maintenance_state.write().await;
+        if *maintenance {
+            messages.error("Another restore is running, try again in a moment!");
+            return Ok(Redirect::to("/contacts/restore").into_response());
+        }
+        *maintenance = true;
+    }
+    let restored = async {
+        let backup = run_backup(&pool, &BackupPolicy::from_env()?, "restore").await?;
+        if backup.status != "Ok" {
+            anyhow::bail!("the safety backup failed: {}", backup.message);
+        }
+        archive.restore(&pool, user.id(), params.mode_p).await
+    }
+    .await;
+    *state.maintenance_state.write().await = false;
+    match restored {
+        Ok(count) => {
+            messages.success(format!(
+                "{} contacts restored from the archive, {} skipped!",
+                count,
+                archive.contacts.len() - count
+            ));
+            Ok(Redirect::to("/contacts/show?page_p=1&birthday_p=0").into_response())
+        }
+        Err(error) => {
+            messages.error(format!("Nothing restored, {}!", error));
+            Ok(Redirect::to("/contacts/restore").into_response())
+        }
+    }
+}
 
-    let pool = state.pool_state.read().await.clone();
+// endregion: RESTORE
 
-    let email_validated =
-        Contact::validate_email(&pool, params.email.as_str(), params.id_p).await?;
-    Ok(email_validated)
+// region: CALENDAR
+
+#[derive(Deserialize)]
+pub struct CalendarParams {
+    pub token_p: String,
+    pub search_p: Option<String>,
+    pub birthday_p: Option<u32>,
+    pub window_p: Option<u32>,
+    /// A saved search of the feed's owner, used instead of the fields above.
+    pub saved_p: Option<i64>,
 }
 
-pub async fn handler_get_count(
-    State(state): State<AppState>, //State(state_contacts): State<ContactState>
-) -> Result<String, AppError> {
-    println!("->> {} - HANDLER: handler_contacts_count", get_time());
-    let pool = state.pool_state.read().await.clone();
+pub fn calendar_router() -> Router<AppState> {
+    Router::new().route("/calendar/birthdays.ics", get(handler_get_birthdays_ics))
+}
 
-    let rec = sqlx::query!(
-        r#"
-            SELECT COUNT(*) as count 
-            FROM contacts_table
-            "#
+/// The birthday feed calendar clients subscribe to. It works without a
+/// session: the token in the URL stands for the user.
+pub async fn handler_get_birthdays_ics(
+    State(state): State<AppState>,
+    Query(params): Query<CalendarParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_birthdays_ics", get_time());
+    let pool = state.pool_state.read().await.clone();
+    let Some(user_id) = CalendarToken::user_id(&pool, &params.token_p).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let (search_p, birthday_p, window_p) = match params.saved_p {
+        Some(saved_id) => {
+            let searches_set = SavedSearch::list_searches(&pool, user_id).await?;
+            let Some(search) = searches_set.iter().find(|search| search.id == saved_id) else {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            };
+            let show_params = search.params()?;
+            (
+                show_params.search_p,
+                show_params.birthday_p,
+                show_params.window_p,
+            )
+        }
+        None => (
+            params.search_p,
+            params.birthday_p.unwrap_or(0),
+            params.window_p,
+        ),
+    };
+    let contacts_set = Contacts::all_contacts(
+        &pool,
+        user_id,
+        search_p.as_deref().unwrap_or(""),
+        birthday_p,
+        "",
+        birthday_window(window_p),
     )
-    .fetch_one(&pool)
     .await?;
-    let contacts_count = rec.count;
-    let span = format!("{} contacts", contacts_count);
-    //thread::sleep(Duration::from_millis(900));
-    Ok(span)
+    let headers = [
+        (header::CONTENT_TYPE, "text/calendar; charset=utf-8"),
+        (
+            header::CONTENT_DISPOSITION,
+            "inline; filename=\"birthdays.ics\"",
+        ),
+    ];
+    Ok((headers, birthdays_calendar(&contacts_set)).into_response())
 }
 
-pub async fn handler_close_flash() -> Result<String, AppError> {
-    println!("->> {} - HANDLER: handler_close_flash", get_time());
-    let span = String::new();
-    Ok(span)
+// endregion: CALENDAR
+
+// region: EXPORT TEMPLATES
+
+#[derive(Template)]
+#[template(path = "export_templates.html")]
+pub struct ExportTemplatesTemplate {
+    pub templates_t: Vec<ExportTemplate>,
+    /// The template in the editor; id 0 while it is a new one.
+    pub template_t: ExportTemplate,
+    pub messages_t: Vec<Message>,
+    pub username: Option<String>,
 }
 
-// endregion: UTILS
+impl ExportTemplatesTemplate {
+    pub fn extensions(&self) -> Vec<&'static str> {
+        TEMPLATE_EXTENSIONS
+            .iter()
+            .map(|(extension, _)| *extension)
+            .collect()
+    }
+}
 
-// region: ARCHIVE
+#[derive(Template)]
+#[template(path = "export_template_preview.html")]
+pub struct ExportTemplatePreviewTemplate {
+    pub output_t: Result<String, String>,
+    pub extension_t: String,
+}
 
+/// The user's templates as extra export buttons on the contacts list.
 #[derive(Template)]
-#[template(path = "archive_ui.html")]
-pub struct ArchiveUiTemplate {
-    pub archive_t: ArchiverState,
+#[template(path = "export_templates_menu.html")]
+pub struct ExportTemplatesMenuTemplate {
+    pub templates_t: Vec<ExportTemplate>,
 }
 
-pub fn archive_router() -> Router<AppState> {
+#[derive(Deserialize)]
+pub struct TemplateIDParams {
+    pub template_p: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct SaveTemplateParams {
+    #[serde(default)]
+    pub id_p: i64,
+    pub name_p: String,
+    pub extension_p: String,
+    pub body_p: String,
+}
+
+#[derive(Deserialize)]
+pub struct PreviewTemplateParams {
+    pub extension_p: String,
+    pub body_p: String,
+}
+
+#[derive(Deserialize)]
+pub struct TemplateExportParams {
+    pub template_p: i64,
+    pub search_p: Option<String>,
+    #[serde(default)]
+    pub birthday_p: u32,
+    pub sort_p: Option<String>,
+    pub window_p: Option<u32>,
+}
+
+pub fn export_templates_router() -> Router<AppState> {
     Router::new()
         .route(
-            "/contacts/archive",
-            get(handler_get_archive).post(handler_post_archive),
+            "/contacts/templates",
+            get(handler_get_templates)
+                .post(handler_post_template)
+                .delete(handler_delete_template),
         )
         .route(
-            "/contacts/archive/file",
-            get(handler_get_archive_file).delete(handler_delete_archive_file),
+            "/contacts/templates/preview",
+            post(handler_post_template_preview),
+        )
+        .route("/contacts/templates/menu", get(handler_get_templates_menu))
+        .route(
+            "/contacts/export/template",
+            get(handler_get_export_template),
         )
 }
 
-pub async fn handler_get_archive(
-    State(archiver_state): State<ArchiverStateType>,
+pub async fn handler_get_templates(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    Query(params): Query<TemplateIDParams>,
 ) -> Result<impl IntoResponse, AppError> {
-    println!("->> {} - HANDLER: handler_get_archive", get_time());
-    let archive_ui_tmpl = ArchiveUiTemplate {
-        archive_t: archiver_state.read().await.clone(),
+    println!("->> {} - HANDLER: handler_get_templates", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/templates").into_response());
     };
-    Ok(archive_ui_tmpl.into_response())
+    let pool = state.pool_state.read().await.clone();
+    let template_set = match params.template_p {
+        Some(id) => ExportTemplate::get(&pool, id, user.id()).await?,
+        None => None,
+    };
+    let templates_tmpl = ExportTemplatesTemplate {
+        templates_t: ExportTemplate::list(&pool, user.id()).await?,
+        template_t: template_set.unwrap_or_else(|| ExportTemplate {
+            extension: "md".to_owned(),
+            body: DEFAULT_EXPORT_TEMPLATE.to_owned(),
+            ..Default::default()
+        }),
+        messages_t: messages.into_iter().collect(),
+        username: Some(user.username),
+    };
+    Ok(templates_tmpl.into_response())
 }
 
-pub async fn handler_get_archive_file(
-    State(state): State<ArchiverStateType>,
+/// Saves a template once its name, type and syntax are fine.
+pub async fn handler_post_template(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    Form(params): Form<SaveTemplateParams>,
 ) -> Result<impl IntoResponse, AppError> {
-    println!("->> {} - HANDLER: handler_get_archive_file", get_time());
-    let archiver = state.read().await.clone();
-    let file = tokio::fs::File::open(archiver.archive_file()).await?;
-    let stream = ReaderStream::new(file);
-    let body = axum::body::Body::from_stream(stream);
-    let headers = [
-        (header::CONTENT_TYPE, "text/html; charset=utf-8"),
-        (
-            header::CONTENT_DISPOSITION,
-            "attachment; filename=\"contacts.db\"",
-        ),
-    ];
-    Ok((headers, body))
+    println!("->> {} - HANDLER: handler_post_template", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let back = match params.id_p {
+        0 => "/contacts/templates".to_owned(),
+        id => format!("/contacts/templates?template_p={}", id),
+    };
+    let name = params.name_p.trim();
+    if name.is_empty() {
+        messages.error("Give the template a name!");
+        return Ok(Redirect::to(&back).into_response());
+    }
+    if !TEMPLATE_EXTENSIONS
+        .iter()
+        .any(|(extension, _)| *extension == params.extension_p)
+    {
+        messages.error(format!("Unknown file type \"{}\"!", params.extension_p));
+        return Ok(Redirect::to(&back).into_response());
+    }
+    if let Err(error) = check_export_template(&params.body_p, &params.extension_p) {
+        messages.error(format!("The template has an error: {}", error));
+        return Ok(Redirect::to(&back).into_response());
+    }
+    let pool = state.pool_state.read().await.clone();
+    let template_set = ExportTemplate {
+        id: params.id_p,
+        user_id: user.id(),
+        name: name.to_owned(),
+        extension: params.extension_p,
+        body: params.body_p,
+        ..Default::default()
+    };
+    match template_set.save(&pool).await {
+        Ok(id) => {
+            messages.success(format!("Template \"{}\" saved!", name));
+            Ok(Redirect::to(&format!("/contacts/templates?template_p={}", id)).into_response())
+        }
+        Err(error) if error.to_string().contains("UNIQUE") => {
+            messages.error(format!("You already have a template named \"{}\"!", name));
+            Ok(Redirect::to(&back).into_response())
+        }
+        Err(error) => Err(error.into()),
+    }
 }
 
-pub async fn handler_post_archive(
-    State(archiver_state): State<ArchiverStateType>, //State(state_archive): State<ArchiverState>
+pub async fn handler_delete_template(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    Query(params): Query<TemplateIDParams>,
 ) -> Result<impl IntoResponse, AppError> {
-    println!("->> {} - HANDLER: handler_post_archive", get_time());
-    if archiver_state.read().await.archive_status == "Waiting" {
-        let mut writable = archiver_state.write().await;
-        writable.archive_status = "Running".to_owned();
-        writable.archive_progress = 0.0;
-        let clone = archiver_state.clone();
-        let _handle = tokio::spawn(async move {
-            run_thread(clone).await;
-        });
+    println!("->> {} - HANDLER: handler_delete_template", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let id = params.template_p.unwrap_or_default();
+    match ExportTemplate::delete(&pool, id, user.id()).await? {
+        0 => messages.error("Delete failed!"),
+        _ => messages.success("Template deleted!"),
     };
-    let archiver_then = archiver_state.read().await.clone();
+    Ok(Redirect::to("/contacts/templates").into_response())
+}
 
-    let archive_ui_tmpl = ArchiveUiTemplate {
-        archive_t: archiver_then,
+/// Renders the editor's template against the first few contacts.
+pub async fn handler_post_template_preview(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    Form(params): Form<PreviewTemplateParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!(
+        "->> {} - HANDLER: handler_post_template_preview",
+        get_time()
+    );
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
     };
-    Ok(archive_ui_tmpl.into_response())
+    let pool = state.pool_state.read().await.clone();
+    let contacts_set = Contacts::search_contacts(
+        &pool,
+        user.id(),
+        "",
+        "",
+        TEMPLATE_PREVIEW_CONTACTS as i64,
+        0,
+    )
+    .await?;
+    let extension = params.extension_p.clone();
+    let output = tokio::task::spawn_blocking(move || {
+        render_export_template(&params.body_p, &params.extension_p, &contacts_set)
+    })
+    .await?;
+    let preview_tmpl = ExportTemplatePreviewTemplate {
+        output_t: output.map_err(|error| error.to_string()),
+        extension_t: extension,
+    };
+    Ok(preview_tmpl.into_response())
 }
 
-pub async fn handler_delete_archive_file(
-    State(state): State<ArchiverStateType>,
+pub async fn handler_get_templates_menu(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
 ) -> Result<impl IntoResponse, AppError> {
-    println!("->> {} - HANDLER: handler_delete_archive_file", get_time());
-    let mut write = state.write().await;
-    write.archive_status = "Waiting".to_owned();
-    drop(write);
-    let archiver = state.read().await.clone();
-    let archive_ui_tmpl = ArchiveUiTemplate {
-        archive_t: archiver,
+    println!("->> {} - HANDLER: handler_get_templates_menu", get_time());
+    let templates_set = match auth_session.user {
+        Some(user) => {
+            let pool = state.pool_state.read().await.clone();
+            ExportTemplate::list(&pool, user.id()).await?
+        }
+        None => Vec::new(),
     };
-    Ok(archive_ui_tmpl.into_response())
+    let menu_tmpl = ExportTemplatesMenuTemplate {
+        templates_t: templates_set,
+    };
+    Ok(menu_tmpl.into_response())
 }
 
-// endregion: ARCHIVE
+/// Runs one of the user's templates over the whole filtered view.
+pub async fn handler_get_export_template(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    ExtraQuery(params): ExtraQuery<TemplateExportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_export_template", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/contacts/templates").into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let Some(template_set) = ExportTemplate::get(&pool, params.template_p, user.id()).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let contacts_set = Contacts::all_contacts(
+        &pool,
+        user.id(),
+        params.search_p.as_deref().unwrap_or(""),
+        params.birthday_p,
+        params.sort_p.as_deref().unwrap_or(""),
+        birthday_window(params.window_p),
+    )
+    .await?;
+    let body = template_set.body.clone();
+    let extension = template_set.extension.clone();
+    let output = tokio::task::spawn_blocking(move || {
+        render_export_template(&body, &extension, &contacts_set)
+    })
+    .await?;
+    match output {
+        Ok(output) => {
+            let headers = [
+                (header::CONTENT_TYPE, template_set.content_type().to_owned()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", template_set.file_name()),
+                ),
+            ];
+            Ok((headers, output).into_response())
+        }
+        Err(error) => {
+            messages.error(format!(
+                "Template \"{}\" failed: {}",
+                template_set.name, error
+            ));
+            Ok(Redirect::to("/contacts/show?page_p=1&birthday_p=0").into_response())
+        }
+    }
+}
+
+// endregion: EXPORT TEMPLATES
+
+// region: ACCOUNT
+
+#[derive(Template)]
+#[template(path = "account.html")]
+pub struct AccountTemplate {
+    pub calendar_t: AccountCalendarTemplate,
+    pub profile_t: UserProfile,
+    pub policy_t: PasswordPolicy,
+    pub messages_t: Vec<Message>,
+    pub username: Option<String>,
+}
+
+impl AccountTemplate {
+    pub fn timezones(&self) -> &'static [&'static str] {
+        timezones()
+    }
+    pub fn is_current_timezone(&self, timezone: &str) -> bool {
+        self.profile_t.timezone == timezone
+    }
+}
+
+/// The birthday feed part of the account page, swapped on its own.
+#[derive(Template)]
+#[template(path = "account_calendar.html")]
+pub struct AccountCalendarTemplate {
+    pub token_t: Option<String>,
+    /// `base_url()`, so the address does not depend on how the page was reached.
+    pub base_url_t: String,
+    /// Each saved search can be subscribed to as a narrower feed.
+    pub searches_t: Vec<SavedSearch>,
+}
+
+impl AccountCalendarTemplate {
+    async fn new(pool: &Pool<Sqlite>, user_id: i64) -> Result<Self, AppError> {
+        Ok(AccountCalendarTemplate {
+            token_t: CalendarToken::get(pool, user_id).await?,
+            base_url_t: base_url()?,
+            searches_t: SavedSearch::list_searches(pool, user_id).await?,
+        })
+    }
+    pub fn feed_url(&self) -> String {
+        format!(
+            "{}/calendar/birthdays.ics?token_p={}",
+            self.base_url_t,
+            self.token_t.as_deref().unwrap_or("")
+        )
+    }
+    /// The feed address with the scheme calendar apps subscribe to.
+    pub fn webcal_url(&self) -> String {
+        let feed_url = self.feed_url();
+        let (_, rest) = feed_url.split_once("://").unwrap_or(("", &feed_url));
+        format!("webcal://{}", rest)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PasswordParams {
+    pub current_password_p: String,
+    pub new_password_p: String,
+    pub confirm_password_p: String,
+}
+
+#[derive(Deserialize)]
+pub struct ProfileParams {
+    pub display_name_p: String,
+    pub email_p: String,
+    pub timezone_p: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountParams {
+    pub password_p: String,
+    /// The username typed again, so the account is not deleted by accident.
+    pub confirm_p: String,
+}
+
+pub fn account_router() -> Router<AppState> {
+    Router::new()
+        .route("/account", get(handler_get_account))
+        .route(
+            "/account/calendar",
+            post(handler_post_account_calendar).delete(handler_delete_account_calendar),
+        )
+        .route("/account/password", post(handler_post_account_password))
+        .route("/account/profile", post(handler_post_account_profile))
+        .route("/account/delete", post(handler_post_account_delete))
+}
+
+pub async fn handler_get_account(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_get_account", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/account").into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    let account_tmpl = AccountTemplate {
+        calendar_t: AccountCalendarTemplate::new(&pool, user.id()).await?,
+        profile_t: user.profile(),
+        policy_t: PasswordPolicy::from_env()?,
+        messages_t: messages.into_iter().collect(),
+        username: Some(user.username),
+    };
+    Ok(account_tmpl.into_response())
+}
+
+/// Checks the current password before setting a new one, then logs this
+/// session in again so only the other sessions are signed out.
+pub async fn handler_post_account_password(
+    State(state): State<AppState>,
+    messages: Messages,
+    mut auth_session: AuthSession,
+    Form(params): Form<PasswordParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!(
+        "->> {} - HANDLER: handler_post_account_password",
+        get_time()
+    );
+    let Some(user) = auth_session.user.clone() else {
+        return Ok(Redirect::to("/login?next=/account").into_response());
+    };
+    let creds = CredentialsParam {
+        username: user.username.clone(),
+        password: params.current_password_p,
+        next: None,
+    };
+    if auth_session.authenticate(creds).await?.is_none() {
+        messages.error("Current password is not correct!");
+        return Ok(Redirect::to("/account").into_response());
+    }
+    let policy_error = PasswordPolicy::from_env()?.check(&user.username, &params.new_password_p);
+    if !policy_error.is_empty() {
+        messages.error(policy_error);
+        return Ok(Redirect::to("/account").into_response());
+    }
+    if params.new_password_p != params.confirm_password_p {
+        messages.error("The new passwords do not match!");
+        return Ok(Redirect::to("/account").into_response());
+    }
+    let pool = state.pool_state.read().await.clone();
+    update_password(&pool, user.id(), &params.new_password_p).await?;
+    let Some(updated) = auth_session.backend.get_user(&user.id()).await? else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    auth_session.login(&updated).await?;
+    messages.success("Password changed, other sessions are signed out!");
+    Ok(Redirect::to("/account").into_response())
+}
+
+pub async fn handler_post_account_profile(
+    State(state): State<AppState>,
+    messages: Messages,
+    auth_session: AuthSession,
+    Form(params): Form<ProfileParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_account_profile", get_time());
+    let Some(user) = auth_session.user else {
+        return Ok(Redirect::to("/login?next=/account").into_response());
+    };
+    let profile = UserProfile {
+        display_name: params.display_name_p.trim().to_owned(),
+        email: params.email_p.trim().to_owned(),
+        timezone: params.timezone_p,
+    };
+    let errors = profile.check();
+    if !errors.is_empty() {
+        errors
+            .into_iter()
+            .fold(messages, |messages, error| messages.error(error));
+        return Ok(Redirect::to("/account").into_response());
+    }
+    let pool = state.pool_state.read().await.clone();
+    profile.save(&pool, user.id()).await?;
+    messages.success("Profile saved!");
+    Ok(Redirect::to("/account").into_response())
+}
+
+/// Deletes the account and answers with the removed contacts as an archive
+/// download, the last thing the user gets from it.
+pub async fn handler_post_account_delete(
+    State(state): State<AppState>,
+    messages: Messages,
+    mut auth_session: AuthSession,
+    Form(params): Form<DeleteAccountParams>,
+) -> Result<impl IntoResponse, AppError> {
+    println!("->> {} - HANDLER: handler_post_account_delete", get_time());
+    let Some(user) = auth_session.user.clone() else {
+        return Ok(Redirect::to("/login?next=/account").into_response());
+    };
+    if params.confirm_p.trim() != user.username {
+        messages.error("Type your username to confirm the deletion!");
+        return Ok(Redirect::to("/account").into_response());
+    }
+    let creds = CredentialsParam {
+        username: user.username.clone(),
+        password: params.password_p,
+        next: None,
+    };
+    if auth_session.authenticate(creds).await?.is_none() {
+        messages.error("Password is not correct!");
+        return Ok(Redirect::to("/account").into_response());
+    }
+    let pool = state.pool_state.read().await.clone();
+    let (file_name, archive) = delete_user(&pool, user.id(), &user.username).await?;
+    CsvImportJob::forget_user(&state.csv_import_state, user.id()).await;
+    auth_session.logout().await?;
+    let disposition = format!("attachment; filename=\"{}\"", file_name);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_owned()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+/// Creates the birthday feed URL, or replaces it so the old one stops working.
+pub async fn handler_post_account_calendar(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!(
+        "->> {} - HANDLER: handler_post_account_calendar",
+        get_time()
+    );
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    CalendarToken::regenerate(&pool, user.id()).await?;
+    Ok(AccountCalendarTemplate::new(&pool, user.id())
+        .await?
+        .into_response())
+}
+
+pub async fn handler_delete_account_calendar(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<impl IntoResponse, AppError> {
+    println!(
+        "->> {} - HANDLER: handler_delete_account_calendar",
+        get_time()
+    );
+    let Some(user) = auth_session.user else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+    let pool = state.pool_state.read().await.clone();
+    CalendarToken::revoke(&pool, user.id()).await?;
+    Ok(AccountCalendarTemplate::new(&pool, user.id())
+        .await?
+        .into_response())
+}
+
+// endregion: ACCOUNT
 
 // region: AUTHFORM - LOGIN
 
@@ -622,8 +3093,8 @@ pub async fn handler_delete_archive_file(
 pub struct LoginTemplate {
     messages: Vec<Message>,
     next: Option<String>,
-    archive_t: ArchiverState,
     username: Option<String>,
+    policy_t: PasswordPolicy,
 }
 // This allows us to extract the "next" field from the query string. We use this
 // to redirect after log in.
@@ -650,10 +3121,9 @@ pub fn userform_login_router() -> Router<AppState> {
 
 pub async fn handler_get_login(
     messages: Messages,
-    State(archiver_state): State<ArchiverStateType>,
     Query(NextUrlParam { next }): Query<NextUrlParam>,
     auth_session: AuthSession,
-) -> LoginTemplate {
+) -> Result<LoginTemplate, AppError> {
     let username: Option<String>;
     if let Some(user) = auth_session.user {
         username = Some(user.username);
@@ -661,12 +3131,12 @@ pub async fn handler_get_login(
         username = None;
     };
 
-    LoginTemplate {
+    Ok(LoginTemplate {
         messages: messages.into_iter().collect(),
         next,
-        archive_t: archiver_state.read().await.clone(),
         username,
-    }
+        policy_t: PasswordPolicy::from_env()?,
+    })
 }
 
 pub async fn handler_get_logout(mut auth_session: AuthSession) -> impl IntoResponse {
@@ -689,7 +3159,11 @@ pub async fn handler_post_login(
 
             let mut login_url = "/login".to_string();
             if let Some(next) = creds.next {
-                login_url = format!("{}?next={}", login_url, next);
+                login_url = format!(
+                    "{}?{}",
+                    login_url,
+                    serde_urlencoded::to_string([("next", next)])?
+                );
             };
 
             return Ok(Redirect::to(&login_url).into_response());
@@ -718,16 +3192,30 @@ pub async fn handler_post_signup(
     let pool = state.pool_state.read().await.clone();
     let username = creds.username;
     let password = creds.password;
+    let login_url = match creds.next {
+        Some(next) => format!("/login?{}", serde_urlencoded::to_string([("next", next)])?),
+        None => "/login".to_string(),
+    };
     let new_error = check_user_errors(&username, &password, &pool).await?;
     match new_error {
-        None => {
-            let id_inserted = create_user(username, password, pool).await?;
-            messages.info(format!("User ID {} Created Successfully!", id_inserted).to_string());
-            Ok(Redirect::to("/login"))
-        }
-        Some(_) => {
-            messages.error("Username or Password not valid!");
-            Ok(Redirect::to("/login"))
+        None => match create_user(username.clone(), password, pool).await {
+            Ok(id_inserted) => {
+                messages.info(format!("User ID {} Created Successfully!", id_inserted).to_string());
+                Ok(Redirect::to(&login_url))
+            }
+            // Someone else signed up with the same name in the meantime.
+            Err(error) if error.to_string().contains("UNIQUE") => {
+                messages.error(format!("Username \"{}\" is already taken", username));
+                Ok(Redirect::to(&login_url))
+            }
+            Err(error) => Err(error.into()),
+        },
+        Some(new_error) => {
+            [new_error.username, new_error.password]
+                .into_iter()
+                .filter(|error| !error.is_empty())
+                .fold(messages, |messages, error| messages.error(error));
+            Ok(Redirect::to(&login_url))
         }
     }
 